@@ -13,6 +13,7 @@ fn main() -> ! {
 
     let mut rcc = dp.RCC.freeze(Config::hsi16());
     let mut adc = dp.ADC.constrain(&mut rcc);
+    adc.calibrate();
     let gpioa = dp.GPIOA.split(&mut rcc);
     let gpiob = dp.GPIOB.split(&mut rcc);
 
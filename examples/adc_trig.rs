@@ -9,7 +9,6 @@ use core::{fmt::Write as _, pin::Pin};
 
 use cortex_m_rt::entry;
 use stm32l0xx_hal::{
-    adc,
     dma::DMA,
     pac::{self, tim2::cr2::MMS_A},
     prelude::*,
@@ -51,10 +50,12 @@ fn main() -> ! {
     static mut BUFFER: [u16; 256] = [0; 256];
     let buffer = Pin::new(unsafe { &mut BUFFER });
 
-    // Start reading ADC values
+    let mut timer = dp.TIM2.timer(1u32.hz(), &mut rcc);
+
+    // Start reading ADC values, triggered by the trigger output of TIM2
     let mut adc = adc.start(
         a0,
-        Some(adc::Trigger::TIM2_TRGO),
+        Some(timer.adc_trigger()),
         &mut dma.handle,
         dma.channels.channel1,
         buffer,
@@ -62,9 +63,7 @@ fn main() -> ! {
 
     // Enable trigger output for TIM2. This must happen after ADC has been
     // configured.
-    dp.TIM2
-        .timer(1u32.hz(), &mut rcc)
-        .select_master_mode(MMS_A::UPDATE);
+    timer.select_master_mode(MMS_A::UPDATE);
 
     loop {
         for val in adc.read_available().unwrap() {
@@ -0,0 +1,85 @@
+#![no_main]
+#![no_std]
+
+extern crate panic_halt;
+
+use core::cell::RefCell;
+use core::ops::DerefMut;
+
+use cortex_m::asm;
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use stm32l0xx_hal::{
+    pac::{self, interrupt, Interrupt},
+    prelude::*,
+    rcc::Config,
+    serial::{self, BufferedSerial},
+};
+
+static SERIAL: Mutex<RefCell<Option<BufferedSerial<pac::USART2>>>> = Mutex::new(RefCell::new(None));
+
+static mut TX_BUFFER: [u8; 64] = [0; 64];
+static mut RX_BUFFER: [u8; 64] = [0; 64];
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut rcc = dp.RCC.freeze(Config::hsi16());
+    let gpioa = dp.GPIOA.split(&mut rcc);
+
+    let serial = dp
+        .USART2
+        .usart(
+            gpioa.pa2,
+            gpioa.pa3,
+            serial::Config::default().baudrate(115_200.Bd()),
+            &mut rcc,
+        )
+        .unwrap();
+
+    // The buffers are filled and drained from the USART2 interrupt.
+    let serial = serial.buffered(unsafe { &mut TX_BUFFER }, unsafe { &mut RX_BUFFER });
+
+    cortex_m::interrupt::free(|cs| {
+        *SERIAL.borrow(cs).borrow_mut() = Some(serial);
+    });
+
+    unsafe {
+        NVIC::unmask(Interrupt::USART2);
+    }
+
+    loop {
+        // Echo what is received on the serial link.
+        cortex_m::interrupt::free(|cs| {
+            if let Some(ref mut serial) = SERIAL.borrow(cs).borrow_mut().deref_mut() {
+                loop {
+                    match serial.read() {
+                        Ok(byte) => {
+                            if serial.write(byte).is_err() {
+                                // The TX buffer is full
+                                break;
+                            }
+                        }
+                        // Errors, including bytes dropped because the RX
+                        // buffer was full, are reported once.
+                        Err(nb::Error::Other(_)) => continue,
+                        Err(nb::Error::WouldBlock) => break,
+                    }
+                }
+            }
+        });
+
+        asm::wfi();
+    }
+}
+
+#[interrupt]
+fn USART2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(ref mut serial) = SERIAL.borrow(cs).borrow_mut().deref_mut() {
+            serial.on_interrupt();
+        }
+    });
+}
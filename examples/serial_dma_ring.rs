@@ -0,0 +1,55 @@
+#![no_main]
+#![no_std]
+
+extern crate panic_halt;
+
+use core::pin::Pin;
+use cortex_m_rt::entry;
+use nb::block;
+use stm32l0xx_hal::{dma::DMA, pac, prelude::*, rcc::Config, serial};
+
+static mut BUFFER: [u8; 64] = [0; 64];
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut rcc = dp.RCC.freeze(Config::hsi16());
+    let mut dma = DMA::new(dp.DMA1, &mut rcc);
+    let gpioa = dp.GPIOA.split(&mut rcc);
+
+    let (mut tx, rx) = dp
+        .USART2
+        .usart(
+            gpioa.pa2,
+            gpioa.pa3,
+            serial::Config::default().baudrate(115_200.Bd()),
+            &mut rcc,
+        )
+        .unwrap()
+        .split();
+
+    // The DMA keeps receiving into the buffer, so no bytes are lost while we
+    // are busy echoing.
+    let buffer = Pin::new(unsafe { &mut BUFFER });
+    let mut rx = rx.read_circular(&mut dma.handle, buffer, dma.channels.channel5);
+
+    loop {
+        // Echo whatever was received, whenever the line becomes idle.
+        if rx.is_idle() {
+            rx.clear_idle_interrupt();
+
+            // Data wrapping around the end of the buffer is handed out in two
+            // pieces. On `Error::BufferOverrun` some data was lost, but
+            // reception continues.
+            for _ in 0..2 {
+                rx.read(|data| {
+                    for &byte in data {
+                        block!(tx.write(byte)).ok();
+                    }
+                })
+                .ok();
+            }
+        }
+    }
+}
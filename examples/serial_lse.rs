@@ -33,7 +33,7 @@ fn main() -> ! {
         .LPUART1
         .usart(tx_pin, rx_pin, serial::Config::default(), &mut rcc)
         .unwrap();
-    serial.use_lse(&mut rcc, &lse);
+    serial.use_lse(&mut rcc, &lse).unwrap();
     let (mut tx, mut rx) = serial.split();
 
     // core::fmt::Write is implemented for tx.
@@ -42,7 +42,7 @@ pub struct Sensor<U> {
 // setup() does all  hal/MCU specific setup and returns generic objects for use in the main code.
 
 use stm32l0xx_hal::{
-    adc::{Adc, Ready, VTemp},
+    adc::{Adc, Ready, SampleTime, VRef, VTemp},
     gpio::{gpiob::PB1, Analog},
     pac::Peripherals,
     prelude::*,
@@ -55,7 +55,15 @@ fn setup() -> (impl ReadTempC, impl ReadTempC + ReadMV, Adc<Ready>) {
 
     let p = Peripherals::take().unwrap();
     let mut rcc = p.RCC.freeze(rcc::Config::hsi16());
-    let adc = p.ADC.constrain(&mut rcc);
+    let mut adc = p.ADC.constrain(&mut rcc);
+    adc.calibrate();
+
+    // The temperature sensor requires a long sampling time
+    adc.set_sample_time(SampleTime::T_160_5);
+
+    // Enable the internal channels used to measure V_DDA and the temperature
+    VRef::new().enable(&mut adc);
+    VTemp::new().enable(&mut adc);
 
     let gpiob = p.GPIOB.split(&mut rcc);
 
@@ -77,8 +85,9 @@ fn setup() -> (impl ReadTempC, impl ReadTempC + ReadMV, Adc<Ready>) {
                 }
 
                 None => {
-                    let v: f32 = a.read(&mut VTemp).unwrap();
-                    (v / 12.412122) as i32 - 50 as i32 //CHECK THIS
+                    // Use the factory calibration values of the sensor
+                    let vdda = a.read_vdda(&mut VRef);
+                    a.read_temperature(&mut VTemp, vdda).unwrap()
                 }
             }
         }
@@ -110,7 +119,7 @@ fn main() -> ! {
 
     loop {
         let mcu_value = mcutemp.read_tempc(&mut adc);
-        hprintln!("inaccurate MCU temp: {}", mcu_value);
+        hprintln!("MCU temp: {} C", mcu_value);
 
         let tmp36_mv: u32 = tmp36.read_mv(&mut adc);
         let tmp36_temp: i32 = tmp36.read_tempc(&mut adc);
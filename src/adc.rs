@@ -1,19 +1,25 @@
 //! # Analog to Digital converter
 
 use core::{
+    cell::{Cell, RefCell},
+    future::Future,
     ops::DerefMut,
     pin::Pin,
     sync::atomic::{compiler_fence, Ordering},
+    task::{Context, Poll, Waker},
 };
 
 use as_slice::AsMutSlice;
+use cortex_m::interrupt::{self, CriticalSection, Mutex};
 
 use crate::{
+    calibration::{self, VrefintCal, VtempCal130, VtempCal30},
     gpio::*,
     hal::adc::{Channel, OneShot},
-    pac::ADC,
-    rcc::{Enable, Rcc},
+    pac::{self, ADC},
+    rcc::{ClockSrc, Clocks, Enable, HSI16Div, PLLSource, Rcc, HSI_FREQ},
 };
+use embedded_time::rate::Hertz;
 
 use crate::dma::{self, Buffer as _};
 
@@ -84,12 +90,194 @@ pub enum SampleTime {
     T_160_5 = 0b111,
 }
 
+/// ADC clock source
+///
+/// See STM32L0x2 reference manual, section 14.3.5.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ClockSource {
+    /// Asynchronous clock, provided by the HSI16 oscillator
+    ///
+    /// HSI16 is enabled when the ADC is powered up, so this works even if the
+    /// system is clocked by MSI or HSE. If the HSI16 divider was enabled
+    /// when freezing the clock configuration, the ADC clock is divided by 4
+    /// too.
+    Hsi16,
+
+    /// Synchronous clock, PCLK divided by 1
+    ///
+    /// This must only be used, if the APB prescaler is not dividing the
+    /// clock. The ADC clock must not exceed 16 MHz, so use
+    /// [`ClockSource::PclkDiv2`] if APB2 is clocked faster than that.
+    Pclk,
+
+    /// Synchronous clock, PCLK divided by 2
+    PclkDiv2,
+
+    /// Synchronous clock, PCLK divided by 4
+    PclkDiv4,
+}
+
+/// ADC scan direction
+///
+/// Determines the order in which the channels of a multi-channel conversion
+/// sequence are converted.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ScanDirection {
+    /// Channels are converted from the lowest to the highest channel number
+    Upward,
+    /// Channels are converted from the highest to the lowest channel number
+    Backward,
+}
+
+/// ADC oversampling ratio
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum OversamplingRatio {
+    /// 2x oversampling
+    X_2 = 0b000,
+    /// 4x oversampling
+    X_4 = 0b001,
+    /// 8x oversampling
+    X_8 = 0b010,
+    /// 16x oversampling
+    X_16 = 0b011,
+    /// 32x oversampling
+    X_32 = 0b100,
+    /// 64x oversampling
+    X_64 = 0b101,
+    /// 128x oversampling
+    X_128 = 0b110,
+    /// 256x oversampling
+    X_256 = 0b111,
+}
+
+/// ADC oversampling right shift
+///
+/// The accumulated result is shifted right by the selected number of bits.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum OversamplingShift {
+    /// No shift
+    S_0 = 0b0000,
+    /// Shift by 1 bit
+    S_1 = 0b0001,
+    /// Shift by 2 bits
+    S_2 = 0b0010,
+    /// Shift by 3 bits
+    S_3 = 0b0011,
+    /// Shift by 4 bits
+    S_4 = 0b0100,
+    /// Shift by 5 bits
+    S_5 = 0b0101,
+    /// Shift by 6 bits
+    S_6 = 0b0110,
+    /// Shift by 7 bits
+    S_7 = 0b0111,
+    /// Shift by 8 bits
+    S_8 = 0b1000,
+}
+
+/// ADC hardware oversampler configuration
+///
+/// The oversampler accumulates `ratio` conversions and shifts the sum right by
+/// `shift` bits, before storing the result in the data register. For example,
+/// `X_256` with `S_4` gives 16-bit results from a 12-bit conversion.
+///
+/// Please note that the result alignment setting is ignored by the hardware
+/// while oversampling is enabled. The results are always right-aligned.
+///
+/// See STM32L0x2 reference manual, section 14.8.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Oversampling {
+    ratio: OversamplingRatio,
+    shift: OversamplingShift,
+    triggered: bool,
+}
+
+impl Oversampling {
+    /// Creates an oversampling configuration with the given ratio and shift
+    pub fn new(ratio: OversamplingRatio, shift: OversamplingShift) -> Self {
+        Self {
+            ratio,
+            shift,
+            triggered: false,
+        }
+    }
+
+    /// Enables triggered oversampling mode
+    ///
+    /// In this mode, each conversion of the oversampling sequence requires a
+    /// new trigger, instead of all conversions being done after the first
+    /// trigger.
+    pub fn triggered(mut self) -> Self {
+        self.triggered = true;
+        self
+    }
+}
+
+/// ADC analog watchdog configuration
+///
+/// The analog watchdog compares each conversion result against a low and a
+/// high threshold. If a result is outside of that window, the watchdog flag is
+/// set and, if enabled with [`Adc::listen`], an interrupt is triggered. The
+/// thresholds are 12-bit values, compared against the raw conversion result.
+///
+/// See STM32L0x2 reference manual, section 14.7.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct AnalogWatchdog {
+    low: u16,
+    high: u16,
+    channel: Option<u8>,
+}
+
+impl AnalogWatchdog {
+    /// Monitors all channels that are being converted
+    pub fn all_channels(low: u16, high: u16) -> Self {
+        Self {
+            low,
+            high,
+            channel: None,
+        }
+    }
+
+    /// Monitors only the given channel
+    pub fn single_channel<C>(_: &C, low: u16, high: u16) -> Self
+    where
+        C: Channel<Adc<Ready>, ID = u8>,
+    {
+        Self {
+            low,
+            high,
+            channel: Some(C::channel()),
+        }
+    }
+}
+
+/// ADC interrupt event
+pub enum Event {
+    /// A conversion result was outside of the analog watchdog window
+    AnalogWatchdog,
+    /// A conversion has finished and its result can be read
+    EndOfConversion,
+    /// All conversions of a sequence have finished
+    EndOfSequence,
+    /// A conversion result was overwritten before it was read
+    Overrun,
+}
+
 /// Analog to Digital converter interface
 pub struct Adc<State> {
     rb: ADC,
     sample_time: SampleTime,
     align: Align,
     precision: Precision,
+    oversampling: Option<Oversampling>,
+    watchdog: Option<AnalogWatchdog>,
+    clocks: Clocks,
+    clock_source: ClockSource,
+    auto_off: bool,
+    wait: bool,
+    scan_direction: ScanDirection,
+    trigger_edge: TriggerEdge,
+    discontinuous: bool,
     _state: State,
 }
 
@@ -104,6 +292,15 @@ impl Adc<Ready> {
             sample_time: SampleTime::T_1_5,
             align: Align::Right,
             precision: Precision::B_12,
+            oversampling: None,
+            watchdog: None,
+            clocks: rcc.clocks,
+            clock_source: ClockSource::Hsi16,
+            auto_off: false,
+            wait: false,
+            scan_direction: ScanDirection::Upward,
+            trigger_edge: TriggerEdge::Rising,
+            discontinuous: false,
             _state: Ready,
         }
     }
@@ -123,6 +320,319 @@ impl Adc<Ready> {
         self.precision = precision;
     }
 
+    /// Set the Adc clock source
+    ///
+    /// The low-frequency mode, which is required for ADC clock frequencies
+    /// below 3.5 MHz, is enabled automatically if necessary. The clock source
+    /// is not checked against the maximum ADC clock of 16 MHz, see
+    /// [`Adc::clock_frequency`].
+    pub fn set_clock_source(&mut self, clock_source: ClockSource) {
+        self.clock_source = clock_source;
+    }
+
+    /// Set the Adc auto-off mode
+    ///
+    /// If enabled, the ADC is powered down automatically when no conversion is
+    /// ongoing, and powered up again when a conversion is started.
+    pub fn set_auto_off(&mut self, auto_off: bool) {
+        self.auto_off = auto_off;
+    }
+
+    /// Set the Adc wait mode
+    ///
+    /// If enabled, a new conversion is only started once the previous
+    /// result has been read from the data register. This avoids overruns,
+    /// if the conversion results are read slower than they are produced.
+    pub fn set_wait(&mut self, wait: bool) {
+        self.wait = wait;
+    }
+
+    /// Set the Adc scan direction
+    ///
+    /// This applies to multi-channel conversions started with
+    /// [`Adc::read_channels`] or [`Adc::start`].
+    pub fn set_scan_direction(&mut self, scan_direction: ScanDirection) {
+        self.scan_direction = scan_direction;
+    }
+
+    /// Set the edge of the hardware trigger that starts a conversion
+    ///
+    /// This only has an effect, if a trigger is passed to [`Adc::start`].
+    pub fn set_trigger_edge(&mut self, edge: TriggerEdge) {
+        self.trigger_edge = edge;
+    }
+
+    /// Set the Adc discontinuous mode
+    ///
+    /// If enabled, each hardware trigger converts only the next channel of the
+    /// sequence, instead of the whole sequence. This only has an effect, if a
+    /// trigger is passed to [`Adc::start`].
+    pub fn set_discontinuous(&mut self, discontinuous: bool) {
+        self.discontinuous = discontinuous;
+    }
+
+    /// Set the Adc hardware oversampling
+    ///
+    /// Pass `None` to disable oversampling. This applies to single conversions
+    /// through the `OneShot` implementation, as well as to continuous
+    /// conversions started with [`Adc::start`].
+    pub fn set_oversampling(&mut self, oversampling: Option<Oversampling>) {
+        self.oversampling = oversampling;
+    }
+
+    /// Set the Adc analog watchdog
+    ///
+    /// Pass `None` to disable the analog watchdog. The watchdog is active
+    /// during single conversions, as well as during continuous conversions
+    /// started with [`Adc::start`].
+    pub fn set_analog_watchdog(&mut self, watchdog: Option<AnalogWatchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    /// Runs the ADC self-calibration sequence
+    ///
+    /// The calibration should be run once after the ADC has been constrained,
+    /// and again if the operating conditions (V_DDA, temperature) change
+    /// significantly. Returns the calibration factor computed by the hardware,
+    /// which can be stored and restored later using
+    /// [`Adc::set_calibration_factor`].
+    ///
+    /// See STM32L0x2 reference manual, section 14.3.3.
+    pub fn calibrate(&mut self) -> u8 {
+        // Calibration can only be started while the ADC is disabled and DMA
+        // requests are disabled.
+        if self.rb.cr.read().aden().bit_is_set() {
+            self.power_down();
+        }
+        self.rb.cfgr1.modify(|_, w| w.dmaen().clear_bit());
+        self.configure_clock();
+
+        self.rb.isr.write(|w| w.eocal().set_bit());
+        self.rb.cr.modify(|_, w| w.adcal().set_bit());
+        while self.rb.isr.read().eocal().bit_is_clear() {}
+        self.rb.isr.write(|w| w.eocal().set_bit());
+
+        self.calibration_factor()
+    }
+
+    /// Returns the calibration factor currently used by the ADC
+    pub fn calibration_factor(&self) -> u8 {
+        self.rb.calfact.read().calfact().bits()
+    }
+
+    /// Restores a previously saved calibration factor
+    ///
+    /// The calibration factor is lost when the ADC analog domain is powered
+    /// down (e.g. in Standby mode, or when the voltage regulator is disabled).
+    /// This method can be used to write back a value returned by
+    /// [`Adc::calibrate`] without running the calibration sequence again.
+    pub fn set_calibration_factor(&mut self, calfact: u8) {
+        // CALFACT can only be written while the ADC is enabled and no
+        // conversion is ongoing.
+        self.power_up();
+        // This is only unsafe for some PAC versions, so we need this to
+        // suppress the warnings.
+        #[allow(unused_unsafe)]
+        self.rb
+            .calfact
+            .write(|w| unsafe { w.calfact().bits(calfact & 0x7f) });
+        self.power_down();
+    }
+
+    /// Measures V_DDA using the internal voltage reference
+    ///
+    /// Returns the analog supply voltage in millivolts, calculated from a
+    /// conversion of the internal voltage reference and its factory
+    /// calibration value. The channel must have been enabled using
+    /// [`VRef::enable`].
+    pub fn read_vdda(&mut self, vref: &mut VRef) -> u16 {
+        let data = self.read_12bit(vref);
+        calibration::vdda_mv(VrefintCal::get().read(), data)
+    }
+
+    /// Converts a channel and returns the result in millivolts
+    ///
+    /// `vdda_mv` is the analog supply voltage in millivolts, as returned by
+    /// [`Adc::read_vdda`].
+    pub fn read_voltage<PIN>(&mut self, pin: &mut PIN, vdda_mv: u16) -> u16
+    where
+        PIN: Channel<Adc<Ready>, ID = u8>,
+    {
+        let data = self.read_12bit(pin);
+        calibration::data_to_mv(data, vdda_mv)
+    }
+
+    /// Measures the die temperature in degrees Celsius
+    ///
+    /// Uses the factory calibration values of the temperature sensor.
+    /// `vdda_mv` is the analog supply voltage in millivolts, as returned by
+    /// [`Adc::read_vdda`]. The channel must have been enabled using
+    /// [`VTemp::enable`].
+    ///
+    /// Please note that the temperature sensor requires a minimum sampling
+    /// time. See the datasheet of your MCU and [`Adc::set_sample_time`].
+    ///
+    /// Returns `None`, if the calibration values are invalid.
+    pub fn read_temperature(&mut self, vtemp: &mut VTemp, vdda_mv: u16) -> Option<i32> {
+        let data = self.read_12bit(vtemp);
+        calibration::temperature_celsius(
+            VtempCal30::get().read(),
+            VtempCal130::get().read(),
+            data,
+            vdda_mv,
+        )
+    }
+
+    /// Reads a 12-bit, right-aligned value, regardless of the configuration
+    ///
+    /// The factory calibration values are 12-bit values, so any conversion
+    /// result that is compared against them needs to be in the same format.
+    fn read_12bit<PIN>(&mut self, pin: &mut PIN) -> u16
+    where
+        PIN: Channel<Adc<Ready>, ID = u8>,
+    {
+        let align = core::mem::replace(&mut self.align, Align::Right);
+        let precision = core::mem::replace(&mut self.precision, Precision::B_12);
+        let oversampling = self.oversampling.take();
+
+        let data: u16 = nb::block!(OneShot::<Adc<Ready>, u16, PIN>::read(self, pin)).unwrap();
+
+        self.align = align;
+        self.precision = precision;
+        self.oversampling = oversampling;
+
+        data
+    }
+
+    /// Converts a set of channels in a single sequence
+    ///
+    /// Blocks until all channels have been converted, without powering down
+    /// the ADC in between conversions. The results are returned in the order
+    /// of conversion, which is ascending channel numbers if the scan direction
+    /// is [`ScanDirection::Upward`], and descending channel numbers if it is
+    /// [`ScanDirection::Backward`].
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `N` is not equal to the number of channels in `channels`.
+    pub fn read_channels<const N: usize>(
+        &mut self,
+        channels: impl Into<Channels>,
+    ) -> Result<[u16; N], Error> {
+        let channels = channels.into();
+        assert_eq!(channels.flags.count_ones() as usize, N);
+
+        // Don't start the next conversion before the previous result has been
+        // read. This makes sure we can't miss any results.
+        let wait = core::mem::replace(&mut self.wait, true);
+
+        self.power_up();
+        self.configure(channels, false, None);
+
+        let mut results = [0; N];
+        let mut overrun = false;
+        for result in results.iter_mut() {
+            loop {
+                let isr = self.rb.isr.read();
+                if isr.ovr().is_overrun() {
+                    overrun = true;
+                    break;
+                }
+                if isr.eoc().bit_is_set() {
+                    break;
+                }
+            }
+            if overrun {
+                self.rb.isr.write(|w| w.ovr().clear());
+                break;
+            }
+
+            // Reading the data register clears the EOC flag
+            *result = self.read_data();
+        }
+
+        self.power_down();
+        self.wait = wait;
+
+        if overrun {
+            return Err(Error::AdcOverrun);
+        }
+
+        Ok(results)
+    }
+
+    /// Starts converting a set of channels without waiting for the results
+    ///
+    /// The results can be read one by one with [`Adc::read_result`], either
+    /// by polling, or from the interrupt handler after enabling
+    /// [`Event::EndOfConversion`] with [`Adc::listen`]. Alternatively,
+    /// [`Adc::next_result`] returns a future that resolves to the next
+    /// result.
+    ///
+    /// A new conversion is only started once the previous result has been
+    /// read, so no results can be lost. The ADC is powered down once the last
+    /// result of the sequence has been read.
+    pub fn start_conversion(&mut self, channels: impl Into<Channels>) {
+        let wait = core::mem::replace(&mut self.wait, true);
+
+        self.power_up();
+        self.configure(channels, false, None);
+
+        self.wait = wait;
+    }
+
+    /// Reads the next result of a conversion started with
+    /// [`Adc::start_conversion`]
+    ///
+    /// Returns `nb::Error::WouldBlock`, if the conversion has not finished
+    /// yet.
+    pub fn read_result(&mut self) -> nb::Result<u16, Error> {
+        let isr = self.rb.isr.read();
+
+        if isr.ovr().is_overrun() {
+            self.rb.isr.write(|w| w.ovr().clear());
+            self.power_down();
+            return Err(nb::Error::Other(Error::AdcOverrun));
+        }
+        if isr.eoc().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Reading the data register clears the EOC flag
+        let value = self.read_data();
+
+        if isr.eos().bit_is_set() {
+            // This was the last conversion of the sequence
+            self.rb.isr.write(|w| w.eos().set_bit());
+            self.power_down();
+        }
+
+        Ok(value)
+    }
+
+    /// Returns a future that resolves to the next result of a conversion
+    /// started with [`Adc::start_conversion`]
+    ///
+    /// The future enables the required ADC interrupts while it is waiting, so
+    /// [`AdcWaker::on_interrupt`] of `waker` must be called from the
+    /// `ADC_COMP` interrupt handler.
+    pub fn next_result<'r>(&'r mut self, waker: &'r AdcWaker) -> NextResult<'r> {
+        NextResult { adc: self, waker }
+    }
+
+    /// Reads the data register, correcting the result alignment if necessary
+    fn read_data(&self) -> u16 {
+        let res = self.rb.dr.read().bits() as u16;
+        if self.align == Align::Left
+            && self.precision == Precision::B_6
+            && self.oversampling.is_none()
+        {
+            res << 8
+        } else {
+            res
+        }
+    }
+
     /// Starts a continuous conversion process
     ///
     /// The `channel` argument specifies which channel should be converted.
@@ -173,11 +683,7 @@ impl Adc<Ready> {
         // `Transfer::new` is going to panic anyway.
         let buffer_unsafe = Buffer {
             ptr: buffer.as_ptr(),
-            len: buffer.len() as u16,
-            pos: 0,
-            dma_pos: 0,
-
-            r_gt_w: false,
+            position: dma::ReadPosition::new(buffer.len() as u16),
         };
 
         // Safe, because the trait bounds of this method guarantee that the
@@ -207,6 +713,15 @@ impl Adc<Ready> {
             sample_time: self.sample_time,
             align: self.align,
             precision: self.precision,
+            oversampling: self.oversampling,
+            watchdog: self.watchdog,
+            clocks: self.clocks,
+            clock_source: self.clock_source,
+            auto_off: self.auto_off,
+            wait: self.wait,
+            scan_direction: self.scan_direction,
+            trigger_edge: self.trigger_edge,
+            discontinuous: self.discontinuous,
             _state: Active {
                 buffer: buffer_unsafe,
                 transfer,
@@ -243,7 +758,133 @@ impl<State> Adc<State> {
         self.rb
     }
 
+    /// Starts listening for an interrupt event
+    ///
+    /// The interrupt is handled by the `ADC_COMP` interrupt handler.
+    pub fn listen(&mut self, event: Event) {
+        // The interrupt handler may modify IER through `AdcWaker`
+        interrupt::free(|_| match event {
+            Event::AnalogWatchdog => self.rb.ier.modify(|_, w| w.awdie().set_bit()),
+            Event::EndOfConversion => self.rb.ier.modify(|_, w| w.eocie().set_bit()),
+            Event::EndOfSequence => self.rb.ier.modify(|_, w| w.eosie().set_bit()),
+            Event::Overrun => self.rb.ier.modify(|_, w| w.ovrie().set_bit()),
+        })
+    }
+
+    /// Stops listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        // The interrupt handler may modify IER through `AdcWaker`
+        interrupt::free(|_| match event {
+            Event::AnalogWatchdog => self.rb.ier.modify(|_, w| w.awdie().clear_bit()),
+            Event::EndOfConversion => self.rb.ier.modify(|_, w| w.eocie().clear_bit()),
+            Event::EndOfSequence => self.rb.ier.modify(|_, w| w.eosie().clear_bit()),
+            Event::Overrun => self.rb.ier.modify(|_, w| w.ovrie().clear_bit()),
+        })
+    }
+
+    /// Returns a pending and enabled `Event`
+    ///
+    /// Multiple `Event`s can be signaled at the same time. In that case, an
+    /// arbitrary pending event will be returned. Clearing the event condition
+    /// will cause this method to return the other pending event(s).
+    ///
+    /// For an event to be returned by this method, it must first be enabled by
+    /// calling `listen`.
+    ///
+    /// This method will never clear a pending event. The end-of-conversion
+    /// event is cleared by reading the result, the other events are cleared by
+    /// [`Adc::read_result`] or [`Adc::clear_analog_watchdog_flag`].
+    pub fn pending_event(&self) -> Option<Event> {
+        let ier = self.rb.ier.read();
+        let isr = self.rb.isr.read();
+
+        if ier.ovrie().bit_is_set() && isr.ovr().bit_is_set() {
+            Some(Event::Overrun)
+        } else if ier.eocie().bit_is_set() && isr.eoc().bit_is_set() {
+            Some(Event::EndOfConversion)
+        } else if ier.eosie().bit_is_set() && isr.eos().bit_is_set() {
+            Some(Event::EndOfSequence)
+        } else if ier.awdie().bit_is_set() && isr.awd().bit_is_set() {
+            Some(Event::AnalogWatchdog)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true`, if a conversion result was outside of the analog
+    /// watchdog window
+    ///
+    /// The flag stays set until it is cleared with
+    /// [`Adc::clear_analog_watchdog_flag`].
+    pub fn is_analog_watchdog_triggered(&self) -> bool {
+        self.rb.isr.read().awd().bit_is_set()
+    }
+
+    /// Clears the analog watchdog flag
+    pub fn clear_analog_watchdog_flag(&mut self) {
+        self.rb.isr.write(|w| w.awd().set_bit());
+    }
+
+    /// Returns the frequency of the ADC clock
+    pub fn clock_frequency(&self) -> Hertz {
+        match self.clock_source {
+            ClockSource::Hsi16 => match self.clocks.source() {
+                // The HSI16 divider applies to the ADC clock as well
+                ClockSrc::HSI16(HSI16Div::Div4)
+                | ClockSrc::PLL(PLLSource::HSI16(HSI16Div::Div4), _, _) => Hertz(HSI_FREQ / 4),
+                _ => Hertz(HSI_FREQ),
+            },
+            ClockSource::Pclk => self.clocks.apb2_clk(),
+            ClockSource::PclkDiv2 => Hertz(self.clocks.apb2_clk().0 / 2),
+            ClockSource::PclkDiv4 => Hertz(self.clocks.apb2_clk().0 / 4),
+        }
+    }
+
+    /// Applies the clock and oversampling configuration
+    ///
+    /// Must only be called while the ADC is disabled.
+    fn configure_clock(&mut self) {
+        if self.clock_source == ClockSource::Hsi16 {
+            // HSI16 might be off if the system is clocked by MSI or HSE. We
+            // don't own RCC here, but only set the oscillator enable bit.
+            let rcc = unsafe { &*pac::RCC::ptr() };
+            rcc.cr.modify(|_, w| w.hsi16on().set_bit());
+            while rcc.cr.read().hsi16rdyf().bit_is_clear() {}
+        }
+
+        // The low-frequency mode must be enabled for ADC clock frequencies
+        // below 3.5 MHz. See STM32L0x2 reference manual, section 14.3.5.
+        let low_frequency = self.clock_frequency().0 < 3_500_000;
+        self.rb.ccr.modify(|_, w| w.lfmen().bit(low_frequency));
+
+        let ckmode = match self.clock_source {
+            ClockSource::Hsi16 => 0b00,
+            ClockSource::PclkDiv2 => 0b01,
+            ClockSource::PclkDiv4 => 0b10,
+            ClockSource::Pclk => 0b11,
+        };
+        let oversampling = self.oversampling;
+        // This is only unsafe for some PAC versions, so we need this to
+        // suppress the warnings.
+        #[allow(unused_unsafe)]
+        self.rb.cfgr2.modify(|_, w| unsafe {
+            w.ckmode().bits(ckmode);
+
+            match oversampling {
+                Some(oversampling) => {
+                    w.ovsr().bits(oversampling.ratio as u8);
+                    w.ovss().bits(oversampling.shift as u8);
+                    w.tovs().bit(oversampling.triggered);
+                    w.ovse().set_bit()
+                }
+                None => w.ovse().clear_bit(),
+            }
+        });
+    }
+
     fn power_up(&mut self) {
+        self.configure_clock();
+
         self.rb.isr.modify(|_, w| w.adrdy().set_bit());
         self.rb.cr.modify(|_, w| w.aden().set_bit());
         while self.rb.isr.read().adrdy().bit_is_clear() {}
@@ -256,20 +897,60 @@ impl<State> Adc<State> {
     }
 
     fn configure(&mut self, channels: impl Into<Channels>, cont: bool, trigger: Option<Trigger>) {
+        if let Some(watchdog) = self.watchdog {
+            // This is only unsafe for some PAC versions, so we need this to
+            // suppress the warnings.
+            #[allow(unused_unsafe)]
+            self.rb.tr.write(|w| unsafe {
+                w.ht().bits(watchdog.high);
+                w.lt().bits(watchdog.low)
+            });
+        }
+
         self.rb.cfgr1.write(|w| {
             w.res().bits(self.precision as u8);
             w.cont().bit(cont);
+            w.scandir()
+                .bit(self.scan_direction == ScanDirection::Backward);
             w.align().bit(self.align == Align::Left);
             // DMA circular mode
             w.dmacfg().set_bit();
             // Generate DMA requests
             w.dmaen().set_bit();
+            w.autoff().bit(self.auto_off);
+            w.wait().bit(self.wait);
 
             if let Some(trigger) = trigger {
                 // Select hardware trigger
                 w.extsel().bits(trigger as u8);
-                // Enable hardware trigger on rising edge
-                w.exten().rising_edge();
+                // Enable hardware trigger on the selected edge
+                match self.trigger_edge {
+                    TriggerEdge::Rising => w.exten().rising_edge(),
+                    TriggerEdge::Falling => w.exten().falling_edge(),
+                    TriggerEdge::Both => w.exten().both_edges(),
+                };
+                // Discontinuous mode is only allowed without continuous mode
+                w.discen().bit(self.discontinuous && !cont);
+            }
+
+            if let Some(watchdog) = self.watchdog {
+                w.awden().set_bit();
+                match watchdog.channel {
+                    Some(channel) => {
+                        // Monitor a single channel
+                        w.awdsgl().set_bit();
+                        // This is only unsafe for some PAC versions, so we
+                        // need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        unsafe {
+                            w.awdch().bits(channel);
+                        }
+                    }
+                    None => {
+                        // Monitor all channels
+                        w.awdsgl().clear_bit();
+                    }
+                }
             }
 
             w
@@ -308,18 +989,121 @@ where
 
         while self.rb.isr.read().eos().bit_is_clear() {}
 
-        let res = self.rb.dr.read().bits() as u16;
-        let val = if self.align == Align::Left && self.precision == Precision::B_6 {
-            res << 8
-        } else {
-            res
-        };
+        let val = self.read_data();
 
         self.power_down();
         Ok(val.into())
     }
 }
 
+/// Connects [`NextResult`] to the `ADC_COMP` interrupt handler
+///
+/// Store it in a `static`, pass it to [`Adc::next_result`] and call
+/// [`AdcWaker::on_interrupt`] from the `ADC_COMP` interrupt handler.
+pub struct AdcWaker {
+    /// Waker of the task waiting for a conversion result
+    waker: Mutex<RefCell<Option<Waker>>>,
+    /// Whether the end-of-conversion and overrun interrupts were enabled by
+    /// the future, rather than by [`Adc::listen`]
+    enabled: Mutex<Cell<(bool, bool)>>,
+}
+
+impl AdcWaker {
+    pub const fn new() -> Self {
+        AdcWaker {
+            waker: Mutex::new(RefCell::new(None)),
+            enabled: Mutex::new(Cell::new((false, false))),
+        }
+    }
+
+    /// Handles the ADC interrupt for [`NextResult`]
+    ///
+    /// Disables the interrupts enabled by the future, and wakes the task
+    /// waiting for the result. Interrupts enabled with [`Adc::listen`] are
+    /// left enabled.
+    pub fn on_interrupt(&self) {
+        let waker = interrupt::free(|cs| {
+            // Safe, as only the interrupts enabled by the future are
+            // disabled, and `Adc` only modifies IER in critical sections too.
+            let adc = unsafe { &*ADC::ptr() };
+            self.disable(cs, adc);
+            self.waker.borrow(cs).take()
+        });
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Enables the interrupts the future waits for
+    fn enable(&self, cs: &CriticalSection, adc: &pac::adc::RegisterBlock) {
+        let ier = adc.ier.read();
+        let (eoc, ovr) = self.enabled.borrow(cs).get();
+        self.enabled.borrow(cs).set((
+            eoc || ier.eocie().bit_is_clear(),
+            ovr || ier.ovrie().bit_is_clear(),
+        ));
+        adc.ier.modify(|_, w| w.eocie().set_bit().ovrie().set_bit());
+    }
+
+    /// Disables the interrupts enabled by `enable`
+    fn disable(&self, cs: &CriticalSection, adc: &pac::adc::RegisterBlock) {
+        let (eoc, ovr) = self.enabled.borrow(cs).replace((false, false));
+        adc.ier.modify(|r, w| {
+            w.eocie()
+                .bit(r.eocie().bit_is_set() && !eoc)
+                .ovrie()
+                .bit(r.ovrie().bit_is_set() && !ovr)
+        });
+    }
+}
+
+impl Default for AdcWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future that resolves to the next conversion result
+///
+/// You can get an instance of this struct by calling [`Adc::next_result`].
+pub struct NextResult<'r> {
+    adc: &'r mut Adc<Ready>,
+    waker: &'r AdcWaker,
+}
+
+impl Future for NextResult<'_> {
+    type Output = Result<u16, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        match this.adc.read_result() {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(nb::Error::Other(err)) => Poll::Ready(Err(err)),
+            Err(nb::Error::WouldBlock) => {
+                // If the conversion finishes between the read above and this,
+                // the interrupt fires right away, so we can't miss it.
+                interrupt::free(|cs| {
+                    *this.waker.waker.borrow(cs).borrow_mut() = Some(cx.waker().clone());
+                    this.waker.enable(cs, &this.adc.rb);
+                });
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for NextResult<'_> {
+    fn drop(&mut self) {
+        interrupt::free(|cs| {
+            self.waker.disable(cs, &self.adc.rb);
+            self.waker.waker.borrow(cs).take();
+        });
+    }
+}
+
 /// Indicates that the ADC peripheral is ready
 pub struct Ready;
 
@@ -363,9 +1147,15 @@ where
 }
 
 /// Hardware triggers that can start an ADC conversion
+///
+/// See STM32L0x2 reference manual, section 14.4.
+#[derive(Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
 pub enum Trigger {
     /// TRG0
+    ///
+    /// Not available on Category 1 and 2 devices.
+    #[cfg(not(any(feature = "io-STM32L021", feature = "io-STM32L031")))]
     TIM6_TRGO = 0b000,
 
     /// TRG1
@@ -378,40 +1168,82 @@ pub enum Trigger {
     TIM2_CH4 = 0b011,
 
     /// TRG4
+    ///
+    /// Not available on Category 1 devices.
+    #[cfg(not(feature = "io-STM32L021"))]
     TIM22_TRGO = 0b100,
 
     /// TRG5
     ///
     /// Only available on Category 5 devices.
-    #[cfg(any(feature = "stm32l072", feature = "stm32l082"))]
+    #[cfg(any(feature = "io-STM32L071", feature = "stm32l072", feature = "stm32l082"))]
     TIM2_CH3 = 0b101,
 
     /// TRG6
+    ///
+    /// Only available on Category 5 devices.
+    #[cfg(not(any(
+        feature = "io-STM32L021",
+        feature = "io-STM32L031",
+        feature = "io-STM32L051"
+    )))]
     TIM3_TRGO = 0b110,
 
     /// TRG7
     EXTI11 = 0b111,
 }
 
+/// Edges of the hardware trigger that start an ADC conversion
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum TriggerEdge {
+    /// Trigger on rising edges only
+    Rising,
+    /// Trigger on falling edges only
+    Falling,
+    /// Trigger on both rising and falling edges
+    Both,
+}
+
+/// Implemented for timers whose trigger output (TRGO) can start an ADC
+/// conversion
+///
+/// Which event is put out on TRGO is selected using the timer's
+/// `select_master_mode` method. See [`crate::timer::Timer::adc_trigger`].
+pub trait TrgoTrigger {
+    /// The ADC trigger that is connected to the timer's TRGO
+    const TRIGGER: Trigger;
+}
+
+impl TrgoTrigger for pac::TIM2 {
+    const TRIGGER: Trigger = Trigger::TIM2_TRGO;
+}
+
+#[cfg(not(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051"
+)))]
+impl TrgoTrigger for pac::TIM3 {
+    const TRIGGER: Trigger = Trigger::TIM3_TRGO;
+}
+
+#[cfg(not(any(feature = "io-STM32L021", feature = "io-STM32L031")))]
+impl TrgoTrigger for pac::TIM6 {
+    const TRIGGER: Trigger = Trigger::TIM6_TRGO;
+}
+
+#[cfg(not(feature = "io-STM32L021"))]
+impl TrgoTrigger for pac::TIM22 {
+    const TRIGGER: Trigger = Trigger::TIM22_TRGO;
+}
+
 /// Provides access to the buffer that the DMA writes ADC values into
 ///
 /// Since the DMA transfer takes ownership of the buffer, we need to access it
 /// with unsafe means. This struct is a safe wrapper around this unsafe access.
 struct Buffer {
     ptr: *const u16,
-    len: u16,
-    pos: u16,
-    dma_pos: u16,
-
-    /// Indicates order of read and write indices
-    ///
-    /// This is initially `false`, indicating that the read position (the `pos`
-    /// field) is smaller than or equal to the write position (internally
-    /// managed by the DMA peripheral).
-    ///
-    /// Once the write position wraps around the buffer boundary, this becomes
-    /// `true` until the read position also wraps around.
-    r_gt_w: bool,
+    position: dma::ReadPosition,
 }
 
 impl Buffer {
@@ -422,12 +1254,12 @@ impl Buffer {
     where
         C: dma::Channel,
     {
-        let transfer_state = self.transfer_state(transfer);
-        if self.check_overrun(transfer_state) {
-            return Some(Err(Error::BufferOverrun));
-        }
+        let write_pos = match self.position.update(transfer) {
+            Some(write_pos) => write_pos,
+            None => return Some(Err(Error::BufferOverrun)),
+        };
 
-        if self.pos == transfer_state.pos {
+        if self.position.pos == write_pos {
             // No overrun detected, but read and write positions are equal. This
             // can only mean that the buffer is empty.
             return None;
@@ -441,15 +1273,14 @@ impl Buffer {
         // The cast is fine too. This is a 32-bit platform, so casting a `u16`
         // to an `isize` will never truncate the value.
         compiler_fence(Ordering::SeqCst);
-        let value = unsafe { *self.ptr.offset(self.pos as isize) };
+        let value = unsafe { *self.ptr.offset(self.position.pos as isize) };
         compiler_fence(Ordering::SeqCst);
 
         // At this point we know that there was no overrun before we started
         // reading, but of course the DMA might have overtaken us since that
         // check. Let's check again. If there's still no overrun, we know that
         // our value is valid.
-        let transfer_state = self.transfer_state(transfer);
-        if self.check_overrun(transfer_state) {
+        if self.position.update(transfer).is_none() {
             // Strictly speaking, the overrun might have happened after our
             // read, and `value` might be valid. No way to know for sure though,
             // so let's assume overrun.
@@ -458,123 +1289,10 @@ impl Buffer {
 
         // Now we know that the value we read is totally fine. Let's advance the
         // read position to finish up here.
-        self.pos = self.pos.wrapping_add(1);
-        if self.pos == 0 || self.pos >= self.len {
-            // We advanced beyond the end of the buffer, which means we need to
-            // wrap around to the beginning.
-            self.pos = 0;
-            self.r_gt_w = false;
-        }
+        self.position.advance(self.position.pos + 1);
 
         Some(Ok(value))
     }
-
-    fn transfer_state<T, C, B>(
-        &self,
-        transfer: &dma::Transfer<T, C, B, dma::Started>,
-    ) -> TransferState
-    where
-        C: dma::Channel,
-    {
-        let (remaining, half, complete) = transfer.state();
-        transfer.clear_flags();
-
-        // Let's translate what we got from the DMA peripheral into a write
-        // position that we can compare with our read position.
-        let pos = self.len - remaining;
-
-        TransferState {
-            pos,
-            half,
-            complete,
-        }
-    }
-
-    fn check_overrun(&mut self, transfer_state: TransferState) -> bool {
-        let overrun = self.check_overrun_inner(transfer_state);
-        self.dma_pos = transfer_state.pos; // Update our state of the DMA
-
-        if overrun {
-            // An overrun occured, but that is not a catastrophic error. Values
-            // got lost, but that doesn't mean we can't read the new values
-            // starting now. Let's get the buffer into a consistent state to
-            // make that possible.
-            //
-            // There are various ways to go about this. What we're doing here is
-            // to throw away all values in the buffer and start again with an
-            // empty buffer, because that minimizes the likelihood of getting
-            // another overrun right away.
-            //
-            // Maybe doing the opposite, setting the read position so that the
-            // buffer is full, to minimize lost values, would be better. But
-            // then we should give the user the option to empty the buffer
-            // manually. I've chosen to go with the simpler option for now.
-            self.pos = transfer_state.pos;
-            self.r_gt_w = false;
-        }
-
-        overrun
-    }
-
-    fn check_overrun_inner(&mut self, transfer_state: TransferState) -> bool {
-        if transfer_state.half && transfer_state.complete {
-            // Each time we attempt a read, we clear both flags. If both flags
-            // are set, then basically anything could have happened in between,
-            // so we have to assume an overrun.
-            //
-            // Please note that it's possible that the DMA has written beyond
-            // the half point and wrapped around, causing both of the flags to
-            // be set, without passing our current reading position. However,
-            // there's no way to distinguish this case from the DMA having
-            // passed those marks multiple times, so we have to be conservative
-            // and assume an overrun.
-            return true;
-        }
-
-        if transfer_state.complete && self.dma_pos < transfer_state.pos {
-            // If the complete flag is set and our previous position is less than
-            // the current position then an overrun must have occurred
-            // This is because the DMA must have wrapped to 0 and then ran past us again
-            return true;
-        }
-
-        // Don't use the transfer complete flag to detect wrap (aside from the overrun above)
-        // There is a timing issue with reading and clearing it so depend on relative positions
-        if transfer_state.pos < self.dma_pos {
-            // The write has wrapped beyond the buffer boundary and started
-            // again at the beginning of the buffer. This is completely normal,
-            // but it affects how we detect an overrun.
-
-            if self.r_gt_w {
-                // The read position was greater than the write position, so if
-                // the write position wrapped, it must have overtaken the read
-                // position. This is an overrun.
-                return true;
-            }
-
-            // The write position has wrapped, so now the read position needs
-            // to be greater than the write position.
-            self.r_gt_w = true;
-        }
-
-        // At this point we know that everything _could_ be alright, judging
-        // from the combination of flags we checked so far. We still need to
-        // compare read and write positions to make sure that we don't actually
-        // have an overrun.
-        if self.r_gt_w {
-            self.pos <= transfer_state.pos
-        } else {
-            self.pos > transfer_state.pos
-        }
-    }
-}
-
-/// Internal struct to represent the current state of the DMA transfer
-#[derive(Clone, Copy, Debug)]
-struct TransferState {
-    pos: u16,
-    half: bool,
-    complete: bool,
 }
 
 /// Iterator over buffered ADC values
@@ -683,15 +1401,22 @@ adc_pins! {
     Channel9: (gpiob::PB1<Analog>, 9u8),
 }
 
-#[cfg(all(feature = "stm32l052", any(feature = "lqfp64", feature = "tfbga64",),))]
-adc_pins! {
-    Channel10: (gpioc::PC0<Analog>, 10u8),
-    Channel11: (gpioc::PC1<Analog>, 11u8),
-    Channel12: (gpioc::PC2<Analog>, 12u8),
+#[cfg(feature = "stm32l0x3")]
+int_adc! {
+    VLcd: (16, vlcden),
 }
 
+// PC0 to PC5 are only available on Category 3 and 5 devices, in packages with
+// enough pins. Please note that the legacy features don't select an `io-*`
+// feature, so they need to be handled separately.
 #[cfg(all(
-    feature = "stm32l072",
+    any(
+        feature = "io-STM32L051",
+        feature = "io-STM32L071",
+        feature = "stm32l052",
+        feature = "stm32l072",
+        feature = "stm32l082",
+    ),
     any(
         feature = "lqfp64",
         feature = "lqfp100",
@@ -707,34 +1432,28 @@ adc_pins! {
     Channel12: (gpioc::PC2<Analog>, 12u8),
 }
 
-#[cfg(all(feature = "stm32l082", feature = "wlcsp49"))]
-adc_pins! {
-    Channel10: (gpioc::PC0<Analog>, 10u8),
-    Channel11: (gpioc::PC1<Analog>, 11u8),
-    Channel12: (gpioc::PC2<Analog>, 12u8),
-}
-
-#[cfg(all(feature = "stm32l052", feature = "lqfp64"))]
-adc_pins! {
-    Channel13: (gpioc::PC3<Analog>, 13u8),
-}
-
 #[cfg(all(
-    feature = "stm32l072",
-    any(feature = "lqfp64", feature = "lqfp100", feature = "ufbga100",),
+    any(
+        feature = "io-STM32L051",
+        feature = "io-STM32L071",
+        feature = "stm32l052",
+        feature = "stm32l072",
+        feature = "stm32l082",
+    ),
+    any(feature = "lqfp64", feature = "lqfp100", feature = "ufbga100"),
 ))]
 adc_pins! {
     Channel13: (gpioc::PC3<Analog>, 13u8),
 }
 
-#[cfg(all(feature = "stm32l052", any(feature = "lqfp64", feature = "tfbga64",),))]
-adc_pins! {
-    Channel14: (gpioc::PC4<Analog>, 14u8),
-    Channel15: (gpioc::PC5<Analog>, 15u8),
-}
-
 #[cfg(all(
-    feature = "stm32l072",
+    any(
+        feature = "io-STM32L051",
+        feature = "io-STM32L071",
+        feature = "stm32l052",
+        feature = "stm32l072",
+        feature = "stm32l082",
+    ),
     any(
         feature = "lqfp64",
         feature = "lqfp100",
@@ -86,3 +86,101 @@ impl VtempCal130 {
         self.0
     }
 }
+
+/// V_DDA at which the factory calibration values were acquired, in millivolts
+pub const VDDA_CALIB_MV: u16 = 3000;
+
+/// Full-scale value of a 12-bit conversion result
+const FULL_SCALE_12BIT: u32 = 4095;
+
+/// Calculates V_DDA in millivolts from a VREFINT conversion result
+///
+/// `vrefint_cal` is the factory calibration value (see [`VrefintCal`]) and
+/// `vrefint_data` is a 12-bit, right-aligned conversion result of the internal
+/// voltage reference channel. Returns 0, if `vrefint_data` is 0.
+pub fn vdda_mv(vrefint_cal: u16, vrefint_data: u16) -> u16 {
+    if vrefint_data == 0 {
+        return 0;
+    }
+
+    // V_DDA = 3 V * VREFINT_CAL / VREFINT_DATA, rounded to the nearest mV
+    let num = u32::from(VDDA_CALIB_MV) * u32::from(vrefint_cal);
+    let den = u32::from(vrefint_data);
+    ((num + den / 2) / den) as u16
+}
+
+/// Converts a conversion result to millivolts
+///
+/// `data` is a 12-bit, right-aligned conversion result and `vdda_mv` is the
+/// analog supply voltage in millivolts, e.g. as returned by [`vdda_mv`].
+pub fn data_to_mv(data: u16, vdda_mv: u16) -> u16 {
+    let num = u32::from(data) * u32::from(vdda_mv);
+    ((num + FULL_SCALE_12BIT / 2) / FULL_SCALE_12BIT) as u16
+}
+
+/// Calculates the die temperature in degrees Celsius
+///
+/// `ts_cal1` and `ts_cal2` are the factory calibration values (see
+/// [`VtempCal30`] and [`VtempCal130`]), `ts_data` is a 12-bit, right-aligned
+/// conversion result of the temperature sensor channel, and `vdda_mv` is the
+/// analog supply voltage in millivolts during that conversion. Returns `None`,
+/// if both calibration values are equal, e.g. because they are invalid.
+pub fn temperature_celsius(ts_cal1: u16, ts_cal2: u16, ts_data: u16, vdda_mv: u16) -> Option<i32> {
+    if ts_cal1 == ts_cal2 {
+        return None;
+    }
+
+    // The calibration values were acquired at V_DDA = 3 V, so the conversion
+    // result needs to be scaled to that voltage first. See STM32L0x2 reference
+    // manual, section 14.10.
+    let ts_data = i32::from(ts_data) * i32::from(vdda_mv) / i32::from(VDDA_CALIB_MV);
+    let ts_cal1 = i32::from(ts_cal1);
+    let ts_cal2 = i32::from(ts_cal2);
+
+    Some((ts_data - ts_cal1) * (130 - 30) / (ts_cal2 - ts_cal1) + 30)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vdda_from_vrefint() {
+        // Conversion at calibration voltage returns calibration voltage
+        assert_eq!(vdda_mv(1655, 1655), 3000);
+
+        // Lower reading means higher supply voltage
+        assert_eq!(vdda_mv(1655, 1505), 3299);
+        assert_eq!(vdda_mv(1655, 2069), 2400);
+
+        // Invalid reading
+        assert_eq!(vdda_mv(1655, 0), 0);
+    }
+
+    #[test]
+    fn data_in_mv() {
+        assert_eq!(data_to_mv(0, 3000), 0);
+        assert_eq!(data_to_mv(4095, 3000), 3000);
+        assert_eq!(data_to_mv(2048, 3300), 1650);
+    }
+
+    #[test]
+    fn temperature() {
+        // Readings at the calibration points
+        assert_eq!(temperature_celsius(670, 860, 670, 3000), Some(30));
+        assert_eq!(temperature_celsius(670, 860, 860, 3000), Some(130));
+
+        // Reading in between
+        assert_eq!(temperature_celsius(670, 860, 765, 3000), Some(80));
+
+        // Same sensor voltage, measured with a different supply voltage
+        assert_eq!(temperature_celsius(670, 860, 609, 3300), Some(30));
+    }
+
+    #[test]
+    fn temperature_invalid_calibration() {
+        // Erased calibration values
+        assert_eq!(temperature_celsius(0xffff, 0xffff, 765, 3000), None);
+        assert_eq!(temperature_celsius(0, 0, 765, 3000), None);
+    }
+}
@@ -219,6 +219,155 @@ where
     pub(crate) fn clear_flags(&self) {
         self.res.channel.clear_flags()
     }
+
+    /// Stops the transfer and returns the owned resources
+    ///
+    /// This is intended for circular transfers, which never finish on their
+    /// own. Data that is currently being transferred might be lost.
+    pub fn stop(self) -> TransferResources<T, C, B> {
+        self.res.channel.stop();
+        self.res.channel.clear_flags();
+
+        compiler_fence(Ordering::SeqCst);
+
+        self.res
+    }
+}
+
+/// Read position in the buffer of a circular transfer
+///
+/// Tracks how far a circular buffer that the DMA writes into has been read,
+/// and detects when the DMA overtakes the read position.
+pub(crate) struct ReadPosition {
+    len: u16,
+
+    /// Read position
+    pub(crate) pos: u16,
+
+    /// Write position of the DMA when it was last checked
+    dma_pos: u16,
+
+    /// Indicates order of read and write indices
+    ///
+    /// This is initially `false`, indicating that the read position (the `pos`
+    /// field) is smaller than or equal to the write position (internally
+    /// managed by the DMA peripheral).
+    ///
+    /// Once the write position wraps around the buffer boundary, this becomes
+    /// `true` until the read position also wraps around.
+    r_gt_w: bool,
+}
+
+impl ReadPosition {
+    pub(crate) fn new(len: u16) -> Self {
+        ReadPosition {
+            len,
+            pos: 0,
+            dma_pos: 0,
+            r_gt_w: false,
+        }
+    }
+
+    /// Returns the current write position of the DMA, or `None` if the DMA
+    /// has overtaken the read position
+    ///
+    /// An overrun is not a catastrophic error. Values got lost, but the new
+    /// values can be read starting now, so all values in the buffer are thrown
+    /// away and reading starts again with an empty buffer. This minimizes the
+    /// likelihood of getting another overrun right away.
+    pub(crate) fn update<T, C, B>(&mut self, transfer: &Transfer<T, C, B, Started>) -> Option<u16>
+    where
+        C: Channel,
+    {
+        let (remaining, half, complete) = transfer.state();
+        transfer.clear_flags();
+
+        // Let's translate what we got from the DMA peripheral into a write
+        // position that we can compare with our read position.
+        let pos = (self.len - remaining) % self.len;
+
+        let overrun = self.check_overrun(pos, half, complete);
+        self.dma_pos = pos; // Update our state of the DMA
+
+        if overrun {
+            self.pos = pos;
+            self.r_gt_w = false;
+            return None;
+        }
+
+        Some(pos)
+    }
+
+    /// Returns the end of the data that can be read in one piece, given the
+    /// write position returned by `update`
+    pub(crate) fn contiguous_end(&self, write_pos: u16) -> u16 {
+        if self.r_gt_w {
+            self.len
+        } else {
+            write_pos
+        }
+    }
+
+    /// Advances the read position, wrapping around at the end of the buffer
+    pub(crate) fn advance(&mut self, pos: u16) {
+        self.pos = pos;
+        if self.pos >= self.len {
+            self.pos = 0;
+            self.r_gt_w = false;
+        }
+    }
+
+    fn check_overrun(&mut self, pos: u16, half: bool, complete: bool) -> bool {
+        if half && complete {
+            // Each time we attempt a read, we clear both flags. If both flags
+            // are set, then basically anything could have happened in between,
+            // so we have to assume an overrun.
+            //
+            // Please note that it's possible that the DMA has written beyond
+            // the half point and wrapped around, causing both of the flags to
+            // be set, without passing our current reading position. However,
+            // there's no way to distinguish this case from the DMA having
+            // passed those marks multiple times, so we have to be conservative
+            // and assume an overrun.
+            return true;
+        }
+
+        if complete && self.dma_pos < pos {
+            // If the complete flag is set and our previous position is less than
+            // the current position then an overrun must have occurred
+            // This is because the DMA must have wrapped to 0 and then ran past us again
+            return true;
+        }
+
+        // Don't use the transfer complete flag to detect wrap (aside from the overrun above)
+        // There is a timing issue with reading and clearing it so depend on relative positions
+        if pos < self.dma_pos {
+            // The write has wrapped beyond the buffer boundary and started
+            // again at the beginning of the buffer. This is completely normal,
+            // but it affects how we detect an overrun.
+
+            if self.r_gt_w {
+                // The read position was greater than the write position, so if
+                // the write position wrapped, it must have overtaken the read
+                // position. This is an overrun.
+                return true;
+            }
+
+            // The write position has wrapped, so now the read position needs
+            // to be greater than the write position.
+            self.r_gt_w = true;
+        }
+
+        // At this point we know that everything _could_ be alright, judging
+        // from the combination of flags we checked so far. We still need to
+        // compare read and write positions to make sure that we don't actually
+        // have an overrun.
+        if self.r_gt_w {
+            self.pos <= pos
+        } else {
+            self.pos > pos
+        }
+    }
 }
 
 pub struct TransferResources<T, C, B> {
@@ -284,6 +433,7 @@ pub trait Channel: Sized {
         Word: SupportedWordSize;
     fn enable_interrupts(&self, interrupts: Interrupts);
     fn start(&self);
+    fn stop(&self);
     fn is_active(&self) -> bool;
     fn clear_complete_flag(&self);
     fn error_occured(&self) -> bool;
@@ -410,6 +560,14 @@ macro_rules! impl_channel {
                     ccr.modify(|_, w| w.en().enabled());
                 }
 
+                fn stop(&self) {
+                    // Safe, because we're only accessing a register that this
+                    // channel has exclusive access to.
+                    let ccr = &unsafe { &*pac::DMA1::ptr() }.$chfield.cr;
+
+                    ccr.modify(|_, w| w.en().disabled());
+                }
+
                 fn is_active(&self) -> bool {
                     // This is safe, for the following reasons:
                     // - We only do one atomic read of ISR.
@@ -522,6 +680,10 @@ impl_target!(
     serial::Tx<USART1>, Channel4, 3;
     serial::Rx<USART1>, Channel3, 3;
     serial::Rx<USART1>, Channel5, 3;
+    serial::Tx<USART1, u16>, Channel2, 3;
+    serial::Tx<USART1, u16>, Channel4, 3;
+    serial::Rx<USART1, u16>, Channel3, 3;
+    serial::Rx<USART1, u16>, Channel5, 3;
 );
 
 #[cfg(any(
@@ -536,6 +698,10 @@ impl_target!(
     serial::Tx<USART2>, Channel7, 4;
     serial::Rx<USART2>, Channel5, 4;
     serial::Rx<USART2>, Channel6, 4;
+    serial::Tx<USART2, u16>, Channel4, 4;
+    serial::Tx<USART2, u16>, Channel7, 4;
+    serial::Rx<USART2, u16>, Channel5, 4;
+    serial::Rx<USART2, u16>, Channel6, 4;
 );
 
 #[cfg(feature = "stm32l0x2")]
@@ -4,17 +4,20 @@ use core::ptr;
 
 use nb::block;
 
-use crate::gpio::{AltMode, PinMode};
+use crate::exti::{DirectLine, Exti};
+use crate::gpio::{AltMode, OpenDrain, Output, PinMode};
 use crate::hal;
 use crate::hal::prelude::*;
 pub use crate::pac::{LPUART1, USART1, USART2, USART4, USART5};
-use crate::rcc::{Enable, Rcc, LSE};
+use crate::rcc::{Enable, Rcc, HSI_FREQ, LSE};
 use embedded_time::rate::{Baud, Extensions};
 
 #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
 use core::{
     ops::{Deref, DerefMut},
     pin::Pin,
+    slice,
+    sync::atomic::{compiler_fence, Ordering},
 };
 
 #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
@@ -48,6 +51,10 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
+    /// Automatic baud rate detection failed
+    AutoBaudRate,
+    /// Data in a DMA ring buffer was overwritten before it was read
+    BufferOverrun,
 }
 
 /// Interrupt event
@@ -66,13 +73,90 @@ pub enum Event {
     Txe,
     /// Idle line state detected.
     Idle,
+    /// LIN break detected.
+    ///
+    /// Only available on USART1 and USART2 in LIN mode. This event is cleared
+    /// by calling `clear_lin_break_flag`.
+    LinBreak,
+    /// The character set with `set_character_match` was received.
+    ///
+    /// This event is cleared by calling `clear_character_match`.
+    CharacterMatch,
+    /// No character was received for the duration set with
+    /// `set_receiver_timeout`.
+    ///
+    /// Only available on USART1 and USART2. This event is cleared by calling
+    /// `clear_receiver_timeout`.
+    ReceiverTimeout,
+}
+
+/// Event that wakes the MCU up from Stop mode
+pub enum WakeupSource {
+    /// Address match, as configured for character match
+    AddressMatch,
+    /// Start bit detected
+    StartBit,
+    /// Received data register not empty
+    RxNotEmpty,
+}
+
+/// Automatic baud rate detection mode
+///
+/// Only supported by USART1 and USART2.
+pub enum AutoBaudMode {
+    /// Measures the duration of the start bit
+    StartBit,
+    /// Measures from falling edge to falling edge, for characters starting
+    /// with `10xx`
+    FallingEdge,
+    /// Detects a `0x7F` character
+    Frame0x7F,
+    /// Detects a `0x55` character
+    Frame0x55,
+}
+
+/// Length of the break detected in LIN mode
+pub enum LinBreakLength {
+    Bits10,
+    Bits11,
+}
+
+/// Event that takes the receiver out of mute mode
+pub enum MuteMode {
+    /// Wakes up when an idle frame is received
+    IdleLine,
+    /// Wakes up when an address character matching the node address is
+    /// received
+    ///
+    /// Address characters have their most significant data bit set.
+    AddressMark(NodeAddress),
+}
+
+/// Node address for address mark wakeup
+///
+/// 7 bit addresses use all data bits below the MSB, which are 6 bits for 7
+/// bit words and 8 bits for 9 bit words.
+pub enum NodeAddress {
+    Bits4(u8),
+    Bits7(u8),
 }
 
 pub enum WordLength {
+    DataBits7,
     DataBits8,
     DataBits9,
 }
 
+/// Oversampling of the receiver
+///
+/// Oversampling by 8 allows higher baud rates at the cost of a lower
+/// tolerance to clock deviations. Not available on LPUART1. Oversampling by 8
+/// is used automatically if the baud rate can't be reached otherwise.
+pub enum Oversampling {
+    By16,
+    By8,
+}
+
 pub enum Parity {
     ParityNone,
     ParityEven,
@@ -90,11 +174,150 @@ pub enum StopBits {
     STOP1P5,
 }
 
+/// Polarity of the driver enable signal
+pub enum DePolarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// RS-485 driver enable configuration
+///
+/// Assertion and deassertion times are given in 1/16 bit time. The hardware
+/// counts them in sample times, which are 1/8 bit time when oversampling by 8,
+/// so they are halved and rounded up in that case. The resulting number of
+/// sample times must not exceed 31, i.e. the times must not exceed 31 when
+/// oversampling by 16, or 62 when oversampling by 8.
+pub struct Rs485 {
+    pub polarity: DePolarity,
+    pub assertion_time: u8,
+    pub deassertion_time: u8,
+}
+
+impl Rs485 {
+    pub fn polarity(mut self, polarity: DePolarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+
+    /// Time between the activation of DE and the start bit
+    pub fn assertion_time(mut self, time: u8) -> Self {
+        self.assertion_time = time;
+        self
+    }
+
+    /// Time between the end of the last stop bit and the deactivation of DE
+    pub fn deassertion_time(mut self, time: u8) -> Self {
+        self.deassertion_time = time;
+        self
+    }
+}
+
+impl Rs485 {
+    /// Converts the assertion and deassertion times to sample times
+    ///
+    /// Returns `None` if they don't fit the DEAT and DEDT fields.
+    fn sample_times(&self, oversampling: &Oversampling) -> Option<(u8, u8)> {
+        let convert = |time: u8| {
+            let time = match oversampling {
+                Oversampling::By16 => time,
+                Oversampling::By8 => time / 2 + time % 2,
+            };
+            if time > 31 {
+                None
+            } else {
+                Some(time)
+            }
+        };
+        Some((
+            convert(self.assertion_time)?,
+            convert(self.deassertion_time)?,
+        ))
+    }
+}
+
+impl Default for Rs485 {
+    fn default() -> Rs485 {
+        Rs485 {
+            polarity: DePolarity::ActiveHigh,
+            assertion_time: 0,
+            deassertion_time: 0,
+        }
+    }
+}
+
+/// IrDA SIR power mode
+pub enum IrdaMode {
+    /// Pulse width of 3/16 bit time
+    Normal,
+    /// Pulse width of three periods of a prescaled clock close to 1.8432 MHz
+    LowPower,
+}
+
+/// ISO 7816 smartcard configuration
+///
+/// The card clock on the CK pin runs at the peripheral clock divided by
+/// `2 * clock_prescaler`. The baud rate is usually set to 1/372 of that
+/// clock.
+pub struct Smartcard {
+    /// Card clock prescaler, from 1 to 31
+    pub clock_prescaler: u8,
+    /// Guard time after each transmitted character, in baud clock periods
+    pub guard_time: u8,
+    /// Sends a NACK on parity errors
+    pub nack: bool,
+    /// Number of automatic retransmissions and receptions, from 0 to 7
+    pub retries: u8,
+}
+
+impl Smartcard {
+    pub fn new(clock_prescaler: u8) -> Self {
+        Smartcard {
+            clock_prescaler,
+            guard_time: 0,
+            nack: true,
+            retries: 3,
+        }
+    }
+
+    pub fn guard_time(mut self, guard_time: u8) -> Self {
+        self.guard_time = guard_time;
+        self
+    }
+
+    pub fn nack(mut self, nack: bool) -> Self {
+        self.nack = nack;
+        self
+    }
+
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+}
+
 pub struct Config {
     pub baudrate: Baud,
     pub wordlength: WordLength,
     pub parity: Parity,
     pub stopbits: StopBits,
+    pub rs485: Option<Rs485>,
+    pub irda: Option<IrdaMode>,
+    pub smartcard: Option<Smartcard>,
+    pub mute: Option<MuteMode>,
+    pub oversampling: Oversampling,
+    /// Swaps the TX and RX pin functions
+    pub swap: bool,
+    /// Inverts the RX pin levels
+    pub rx_invert: bool,
+    /// Inverts the TX pin levels
+    pub tx_invert: bool,
+    /// Inverts the data bits, including parity
+    pub data_invert: bool,
+    /// Transmits and receives the most significant bit first
+    pub msb_first: bool,
+    /// Maximum deviation of the achieved from the requested baud rate, in
+    /// parts per million
+    pub baudrate_tolerance: u32,
 }
 
 impl Config {
@@ -128,10 +351,89 @@ impl Config {
         self
     }
 
+    pub fn wordlength_7(mut self) -> Self {
+        self.wordlength = WordLength::DataBits7;
+        self
+    }
+
+    pub fn oversampling(mut self, oversampling: Oversampling) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    pub fn swap_pins(mut self) -> Self {
+        self.swap = true;
+        self
+    }
+
+    pub fn invert_rx(mut self) -> Self {
+        self.rx_invert = true;
+        self
+    }
+
+    pub fn invert_tx(mut self) -> Self {
+        self.tx_invert = true;
+        self
+    }
+
+    pub fn invert_data(mut self) -> Self {
+        self.data_invert = true;
+        self
+    }
+
+    pub fn msb_first(mut self) -> Self {
+        self.msb_first = true;
+        self
+    }
+
+    /// Sets the maximum baud rate error in parts per million
+    pub fn baudrate_tolerance(mut self, ppm: u32) -> Self {
+        self.baudrate_tolerance = ppm;
+        self
+    }
+
     pub fn stopbits(mut self, stopbits: StopBits) -> Self {
         self.stopbits = stopbits;
         self
     }
+
+    /// Enables the hardware driver enable output for RS-485 transceivers
+    ///
+    /// The DE pin is passed to the `usart_rs485` constructor.
+    pub fn rs485(mut self, rs485: Rs485) -> Self {
+        self.rs485 = Some(rs485);
+        self
+    }
+
+    /// Enables the IrDA SIR encoder and decoder
+    ///
+    /// Only supported by USART1 and USART2, at baud rates up to 115200 and
+    /// with 1 stop bit.
+    pub fn irda(mut self, mode: IrdaMode) -> Self {
+        self.irda = Some(mode);
+        self
+    }
+
+    /// Enables ISO 7816 smartcard mode
+    ///
+    /// Only supported by USART1 and USART2. Requires 9 bit words with parity
+    /// and 1.5 stop bits. The CK pin is passed to the `usart_smartcard`
+    /// constructor.
+    pub fn smartcard(mut self, smartcard: Smartcard) -> Self {
+        self.smartcard = Some(smartcard);
+        self
+    }
+
+    /// Enables multiprocessor mute mode
+    ///
+    /// The receiver is muted with `Serial::enter_mute`. The node address of
+    /// address mark wakeup is stored in the same register field as the
+    /// character of `Serial::set_character_match`, which can't be used
+    /// together with it.
+    pub fn mute(mut self, mode: MuteMode) -> Self {
+        self.mute = Some(mode);
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -145,6 +447,17 @@ impl Default for Config {
             wordlength: WordLength::DataBits8,
             parity: Parity::ParityNone,
             stopbits: StopBits::STOP1,
+            rs485: None,
+            irda: None,
+            smartcard: None,
+            mute: None,
+            oversampling: Oversampling::By16,
+            swap: false,
+            rx_invert: false,
+            tx_invert: false,
+            data_invert: false,
+            msb_first: false,
+            baudrate_tolerance: 20_000,
         }
     }
 }
@@ -159,8 +472,46 @@ pub trait RxPin<USART> {
     fn setup(&self);
 }
 
-/// Macro to implement `TxPin` / `RxPin` for a certain pin, using a certain
-/// alternative function and for a certain serial peripheral.
+/// Trait to mark serial pins with request-to-send capability.
+pub trait RtsPin<USART> {
+    fn setup(&self);
+}
+
+/// Trait to mark serial pins with clear-to-send capability.
+pub trait CtsPin<USART> {
+    fn setup(&self);
+}
+
+/// Trait to mark serial pins with clock output capability.
+pub trait CkPin<USART> {
+    fn setup(&self);
+}
+
+/// Trait to mark serial pins with driver enable capability.
+///
+/// The driver enable output shares its pins with RTS.
+pub trait DePin<USART> {
+    fn setup(&self);
+}
+
+impl<USART, PIN> DePin<USART> for PIN
+where
+    PIN: RtsPin<USART>,
+{
+    fn setup(&self) {
+        RtsPin::setup(self);
+    }
+}
+
+/// Trait to mark open-drain transmit pins usable in single-wire half-duplex
+/// mode.
+pub trait HalfDuplexPin<USART>: TxPin<USART> {}
+
+/// Macro to implement `TxPin` / `RxPin` / `RtsPin` / `CtsPin` / `CkPin` for a certain
+/// pin, using a certain alternative function and for a certain serial
+/// peripheral.
+///
+/// Open-drain transmit pins additionally implement `HalfDuplexPin`.
 macro_rules! impl_pins {
     ($($pin:ident, $alt:ident, $instance:ty, $trait:ident;)*) => {
         $(
@@ -169,26 +520,67 @@ macro_rules! impl_pins {
                     self.set_alt_mode(AltMode::$alt);
                 }
             }
+
+            impl_pins!(@half_duplex $pin, $instance, $trait);
         )*
-    }
+    };
+    (@half_duplex $pin:ident, $instance:ty, TxPin) => {
+        impl HalfDuplexPin<$instance> for $pin<Output<OpenDrain>> {}
+    };
+    (@half_duplex $pin:ident, $instance:ty, $trait:ident) => {};
+}
+
+/// Extension trait to create a smartcard interface
+pub trait SerialSmartcardExt<TX, CK>: Sized {
+    /// Creates a serial interface in ISO 7816 smartcard mode
+    ///
+    /// The smartcard settings are taken from `config.smartcard`. The TX pin
+    /// must be configured as open-drain output and carries the card I/O line.
+    fn usart_smartcard(
+        self,
+        tx: TX,
+        ck: CK,
+        config: Config,
+        rcc: &mut Rcc,
+    ) -> Result<Serial<Self>, InvalidConfig>;
+}
+
+/// Extension trait to create a single-wire half-duplex serial interface
+pub trait SerialHalfDuplexExt<TX>: Sized {
+    /// Creates a serial interface communicating over the TX pin only
+    ///
+    /// The pin must be configured as open-drain output and needs an external
+    /// pull-up. Transmitter and receiver share the line and the USART switches
+    /// between them automatically.
+    fn usart_half_duplex(
+        self,
+        tx: TX,
+        config: Config,
+        rcc: &mut Rcc,
+    ) -> Result<Serial<Self>, InvalidConfig>;
 }
 
 #[cfg(feature = "io-STM32L021")]
 impl_pins!(
     PA0, AF0, USART2, RxPin;
+    PA0, AF4, USART2, CtsPin;
     PA0, AF6, LPUART1, RxPin;
+    PA1, AF4, USART2, RtsPin;
     PA1, AF6, LPUART1, TxPin;
     PA2, AF4, USART2, TxPin;
     PA2, AF6, LPUART1, TxPin;
     PA3, AF4, USART2, RxPin;
     PA3, AF6, LPUART1, RxPin;
+    PA4, AF4, USART2, CkPin;
     PA4, AF6, LPUART1, TxPin;
+    PA6, AF4, LPUART1, CtsPin;
     PA9, AF4, USART2, TxPin;
     PA10, AF4, USART2, RxPin;
     PA13, AF6, LPUART1, RxPin;
     PA14, AF4, USART2, TxPin;
     PA14, AF6, LPUART1, TxPin;
     PA15, AF4, USART2, RxPin;
+    PB1, AF4, LPUART1, RtsPin;
     PB6, AF0, USART2, TxPin;
     PB6, AF6, LPUART1, TxPin;
     PB7, AF0, USART2, RxPin;
@@ -198,16 +590,23 @@ impl_pins!(
 
 #[cfg(feature = "io-STM32L031")]
 impl_pins!(
+    PA0, AF4, USART2, CtsPin;
+    PA1, AF4, USART2, RtsPin;
     PA2, AF4, USART2, TxPin;
     PA2, AF6, LPUART1, TxPin;
     PA3, AF4, USART2, RxPin;
     PA3, AF6, LPUART1, RxPin;
+    PA4, AF4, USART2, CkPin;
+    PA6, AF4, LPUART1, CtsPin;
     PA9, AF4, USART2, TxPin;
     PA10, AF4, USART2, RxPin;
+    PA11, AF4, USART2, CtsPin;
+    PA12, AF4, USART2, RtsPin;
     PA13, AF6, LPUART1, RxPin;
     PA14, AF4, USART2, TxPin;
     PA14, AF6, LPUART1, TxPin;
     PA15, AF4, USART2, RxPin;
+    PB1, AF4, LPUART1, RtsPin;
     PB6, AF0, USART2, TxPin;
     PB7, AF0, USART2, RxPin;
     PB10, AF6, LPUART1, TxPin;
@@ -217,16 +616,26 @@ impl_pins!(
 
 #[cfg(feature = "io-STM32L051")]
 impl_pins!(
+    PA0, AF4, USART2, CtsPin;
+    PA1, AF4, USART2, RtsPin;
     PA2, AF4, USART2, TxPin;
     PA3, AF4, USART2, RxPin;
+    PA4, AF4, USART2, CkPin;
+    PA6, AF4, LPUART1, CtsPin;
+    PA8, AF4, USART1, CkPin;
     PA9, AF4, USART1, TxPin;
     PA10, AF4, USART1, RxPin;
+    PA11, AF4, USART1, CtsPin;
+    PA12, AF4, USART1, RtsPin;
     PA14, AF4, USART2, TxPin;
     PA15, AF4, USART2, RxPin;
+    PB1, AF4, LPUART1, RtsPin;
     PB6, AF0, USART1, TxPin;
     PB7, AF0, USART1, RxPin;
     PB10, AF4, LPUART1, TxPin;
     PB11, AF4, LPUART1, RxPin;
+    PB13, AF4, LPUART1, CtsPin;
+    PB14, AF4, LPUART1, RtsPin;
     PC4, AF2, LPUART1, TxPin;
     PC5, AF2, LPUART1, RxPin;
     PC10, AF0, LPUART1, TxPin;
@@ -235,26 +644,41 @@ impl_pins!(
 
 #[cfg(feature = "io-STM32L071")]
 impl_pins!(
+    PA0, AF4, USART2, CtsPin;
     PA0, AF6, USART4, TxPin;
+    PA1, AF4, USART2, RtsPin;
     PA1, AF6, USART4, RxPin;
     PA2, AF4, USART2, TxPin;
     PA2, AF6, LPUART1, TxPin;
     PA3, AF4, USART2, RxPin;
     PA3, AF6, LPUART1, RxPin;
+    PA4, AF4, USART2, CkPin;
+    PA6, AF4, LPUART1, CtsPin;
+    PA8, AF4, USART1, CkPin;
     PA9, AF4, USART1, TxPin;
     PA10, AF4, USART1, RxPin;
+    PA11, AF4, USART1, CtsPin;
+    PA12, AF4, USART1, RtsPin;
     PA13, AF6, LPUART1, RxPin;
     PA14, AF4, USART2, TxPin;
     PA14, AF6, LPUART1, TxPin;
     PA15, AF4, USART2, RxPin;
+    PA15, AF6, USART4, RtsPin;
+    PB1, AF4, LPUART1, RtsPin;
+    PB3, AF5, USART1, RtsPin;
     PB3, AF6, USART5, TxPin;
+    PB4, AF5, USART1, CtsPin;
     PB4, AF6, USART5, RxPin;
+    PB5, AF6, USART5, RtsPin;
     PB6, AF0, USART1, TxPin;
     PB7, AF0, USART1, RxPin;
+    PB7, AF6, USART4, CtsPin;
     PB10, AF4, LPUART1, TxPin;
     PB10, AF7, LPUART1, RxPin;
     PB11, AF4, LPUART1, RxPin;
     PB11, AF7, LPUART1, TxPin;
+    PB13, AF4, LPUART1, CtsPin;
+    PB14, AF4, LPUART1, RtsPin;
     PC0, AF6, LPUART1, RxPin;
     PC1, AF6, LPUART1, TxPin;
     PC4, AF2, LPUART1, TxPin;
@@ -265,31 +689,278 @@ impl_pins!(
     PC11, AF6, USART4, RxPin;
     PC12, AF2, USART5, TxPin;
     PD2, AF6, USART5, RxPin;
+    PD3, AF0, USART2, CtsPin;
+    PD4, AF0, USART2, RtsPin;
     PD5, AF0, USART2, TxPin;
     PD6, AF0, USART2, RxPin;
     PD8, AF0, LPUART1, TxPin;
     PD9, AF0, LPUART1, RxPin;
+    PD11, AF0, LPUART1, CtsPin;
+    PD12, AF0, LPUART1, RtsPin;
     PE8, AF6, USART4, TxPin;
     PE9, AF6, USART4, RxPin;
     PE10, AF6, USART5, TxPin;
     PE11, AF6, USART5, RxPin;
 );
 
+/// Baud rate divisor
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Divisor {
+    /// Value of the BRR register
+    brr: u32,
+    /// Baud rate resulting from the divisor
+    baudrate: u32,
+}
+
+impl Divisor {
+    /// Calculates the USART divisor for `baudrate`
+    ///
+    /// Returns `None` if the divisor is out of range.
+    fn usart(clock: u32, baudrate: u32, over8: bool) -> Option<Self> {
+        if baudrate == 0 {
+            return None;
+        }
+        let clock = u64::from(clock);
+        let baudrate = u64::from(baudrate);
+
+        let div = if over8 {
+            // BRR[3] must be cleared, so only even divisors are possible
+            (2 * clock + baudrate) / (2 * baudrate) * 2
+        } else {
+            (clock + baudrate / 2) / baudrate
+        };
+        if !(16..=0xffff).contains(&div) {
+            return None;
+        }
+        let div = div as u32;
+
+        if over8 {
+            Some(Divisor {
+                brr: (div & !0xf) | ((div & 0xf) >> 1),
+                baudrate: (2 * clock / u64::from(div)) as u32,
+            })
+        } else {
+            Some(Divisor {
+                brr: div,
+                baudrate: (clock / u64::from(div)) as u32,
+            })
+        }
+    }
+
+    /// Calculates the LPUART divisor for `baudrate`
+    ///
+    /// Returns `None` if the divisor is out of range.
+    fn lpuart(clock: u32, baudrate: u32) -> Option<Self> {
+        if baudrate == 0 {
+            return None;
+        }
+        let clock = 256 * u64::from(clock);
+        let baudrate = u64::from(baudrate);
+
+        let brr = (clock + baudrate / 2) / baudrate;
+        if !(0x300..=0xf_ffff).contains(&brr) {
+            return None;
+        }
+
+        Some(Divisor {
+            brr: brr as u32,
+            baudrate: (clock / brr) as u32,
+        })
+    }
+
+    /// Calculates the baud rate of a USART from the BRR register value
+    fn usart_baudrate(clock: u32, brr: u32, over8: bool) -> u32 {
+        if over8 {
+            let div = (brr & !0xf) | ((brr & 0x7) << 1);
+            (2 * u64::from(clock) / u64::from(div.max(1))) as u32
+        } else {
+            clock / brr.max(1)
+        }
+    }
+
+    /// Returns the deviation from `baudrate` in parts per million
+    fn error_ppm(&self, baudrate: u32) -> u32 {
+        let diff = u64::from(self.baudrate.max(baudrate) - self.baudrate.min(baudrate));
+        (diff * 1_000_000 / u64::from(baudrate.max(1))) as u32
+    }
+}
+
 /// Serial abstraction
 pub struct Serial<USART> {
     usart: USART,
     rx: Rx<USART>,
     tx: Tx<USART>,
+    config: Config,
+    clock: u32,
+    /// Whether the kernel clock keeps running in Stop mode (HSI16 or LSE)
+    stop_mode_clock: bool,
 }
 
 /// Serial receiver
-pub struct Rx<USART> {
-    _usart: PhantomData<USART>,
+///
+/// Receives `u8` words by default. Use `with_u16_data` to receive 9 bit words.
+pub struct Rx<USART, WORD = u8> {
+    _usart: PhantomData<(USART, WORD)>,
 }
 
 /// Serial transmitter
-pub struct Tx<USART> {
-    _usart: PhantomData<USART>,
+///
+/// Transmits `u8` words by default. Use `with_u16_data` to transmit 9 bit
+/// words.
+pub struct Tx<USART, WORD = u8> {
+    _usart: PhantomData<(USART, WORD)>,
+}
+
+/// Serial receiver writing into a circular DMA buffer
+///
+/// Created by `Rx::read_circular`. The DMA keeps receiving into the buffer
+/// without gaps, and `read` hands out the data received in the meantime. Call
+/// it whenever the line becomes idle (see `Event::Idle`) and on the half
+/// transfer and transfer complete interrupts of the DMA channel, so the buffer
+/// never fills up between two reads.
+#[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
+pub struct RingBuffer<USART, Channel, Buffer> {
+    transfer: dma::Transfer<Rx<USART>, Channel, Buffer, dma::Started>,
+    ptr: *const u8,
+    position: dma::ReadPosition,
+}
+
+#[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
+impl<USART, Channel, Buffer> RingBuffer<USART, Channel, Buffer>
+where
+    Channel: dma::Channel,
+{
+    /// Passes the data received since the last call to `f`
+    ///
+    /// `f` is called with the longest contiguous slice of received data. If
+    /// the data wraps around the end of the buffer, the rest is passed on the
+    /// next call. The data is consumed once `f` returns.
+    ///
+    /// Returns `Error::BufferOverrun` if the DMA overwrote data that wasn't
+    /// read yet, including data passed to `f` during this call. The buffer
+    /// is then emptied and reception continues with the next byte.
+    pub fn read<R>(&mut self, f: impl FnOnce(&[u8]) -> R) -> Result<R, Error> {
+        let write_pos = self.update()?;
+        let pos = self.position.pos;
+        let end = self.position.contiguous_end(write_pos);
+
+        compiler_fence(Ordering::SeqCst);
+
+        // Safe, because the data between the read and write positions is not
+        // written by the DMA until the read position advances. If it is
+        // anyway, the overrun is detected below.
+        let data =
+            unsafe { slice::from_raw_parts(self.ptr.add(pos as usize), (end - pos) as usize) };
+        let result = f(data);
+
+        compiler_fence(Ordering::SeqCst);
+
+        // Make sure the data wasn't overwritten while `f` processed it
+        self.update()?;
+
+        self.position.advance(end);
+
+        Ok(result)
+    }
+
+    /// Stops the reception and returns the owned resources
+    ///
+    /// Data that wasn't read yet is discarded.
+    pub fn stop(self) -> dma::TransferResources<Rx<USART>, Channel, Buffer> {
+        self.transfer.stop()
+    }
+
+    /// Returns the current write position of the DMA, or an error if the
+    /// DMA has overtaken the read position
+    fn update(&mut self) -> Result<u16, Error> {
+        self.position
+            .update(&self.transfer)
+            .ok_or(Error::BufferOverrun)
+    }
+}
+
+/// Interrupt-driven serial interface with software queues
+///
+/// Received bytes are stored in the RX queue and queued bytes are sent from
+/// the TX queue by `on_interrupt`, which must be called from the interrupt
+/// handler of the USART. To share it with the interrupt handler, store it in
+/// a `Mutex<RefCell<_>>`.
+///
+/// `write` and `flush` return `WouldBlock` until the interrupt handler made
+/// progress. Don't block on them inside a critical section, as the handler
+/// can't run there and they would never complete. Release the critical
+/// section between attempts instead.
+///
+/// Created by `Serial::buffered`.
+pub struct BufferedSerial<USART> {
+    serial: Serial<USART>,
+    tx: Queue<'static>,
+    rx: Queue<'static>,
+    /// First error that wasn't returned by `read` yet
+    error: Option<Error>,
+}
+
+impl<USART> BufferedSerial<USART> {
+    /// Returns the number of received bytes that weren't read yet
+    pub fn rx_len(&self) -> usize {
+        self.rx.len
+    }
+
+    /// Returns the number of bytes waiting to be sent
+    pub fn tx_len(&self) -> usize {
+        self.tx.len
+    }
+
+    /// Records an error, unless a previous error wasn't returned yet
+    fn report(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Ring buffer of a `BufferedSerial`
+struct Queue<'a> {
+    buffer: &'a mut [u8],
+    /// Position of the oldest byte
+    pos: usize,
+    len: usize,
+}
+
+impl<'a> Queue<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Queue {
+            buffer,
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends a byte, returns false if the queue is full
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == self.buffer.len() {
+            return false;
+        }
+        let end = (self.pos + self.len) % self.buffer.len();
+        self.buffer[end] = byte;
+        self.len += 1;
+        true
+    }
+
+    /// Removes the oldest byte
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.pos];
+        self.pos = (self.pos + 1) % self.buffer.len();
+        self.len -= 1;
+        Some(byte)
+    }
+
+    fn release(self) -> &'a mut [u8] {
+        self.buffer
+    }
 }
 
 macro_rules! usart {
@@ -299,6 +970,14 @@ macro_rules! usart {
         $(
             pub trait $SerialExt<TX, RX> {
                 fn usart(self, tx: TX, rx: RX, config: Config, rcc: &mut Rcc) -> Result<Serial<$USARTX>, InvalidConfig>;
+
+                /// Creates a serial interface driving an RS-485 transceiver
+                ///
+                /// The driver enable settings are taken from `config.rs485`,
+                /// or the defaults are used if none are given.
+                fn usart_rs485<DE>(self, tx: TX, rx: RX, de: DE, config: Config, rcc: &mut Rcc) -> Result<Serial<$USARTX>, InvalidConfig>
+                where
+                    DE: DePin<$USARTX>;
             }
 
             impl<TX, RX> $SerialExt<TX, RX> for $USARTX
@@ -309,6 +988,29 @@ macro_rules! usart {
                 fn usart(self, tx: TX, rx: RX, config: Config, rcc: &mut Rcc) -> Result<Serial<$USARTX>, InvalidConfig> {
                     Serial::$usartX(self, tx, rx, config, rcc)
                 }
+
+                fn usart_rs485<DE>(self, tx: TX, rx: RX, de: DE, mut config: Config, rcc: &mut Rcc) -> Result<Serial<$USARTX>, InvalidConfig>
+                where
+                    DE: DePin<$USARTX>,
+                {
+                    de.setup();
+                    if config.rs485.is_none() {
+                        config.rs485 = Some(Rs485::default());
+                    }
+                    Serial::$usartX(self, tx, rx, config, rcc)
+                }
+            }
+
+            impl<TX> SerialHalfDuplexExt<TX> for $USARTX
+                where
+                    TX: HalfDuplexPin<$USARTX>,
+            {
+                fn usart_half_duplex(self, tx: TX, config: Config, rcc: &mut Rcc) -> Result<Serial<$USARTX>, InvalidConfig> {
+                    let mut serial = Serial::<$USARTX>::init(self, config, rcc)?;
+                    tx.setup();
+                    serial.reconfigure(|usart| usart.cr3.modify(|_, w| w.hdsel().set_bit()));
+                    Ok(serial)
+                }
             }
 
             impl Serial<$USARTX> {
@@ -323,28 +1025,78 @@ macro_rules! usart {
                     TX: TxPin<$USARTX>,
                     RX: RxPin<$USARTX>,
                 {
+                    let serial = Self::init(usart, config, rcc)?;
                     tx.setup();
                     rx.setup();
+                    Ok(serial)
+                }
+
+                fn init(usart: $USARTX, mut config: Config, rcc: &mut Rcc) -> Result<Self, InvalidConfig> {
+                    if let Some(MuteMode::AddressMark(address)) = &config.mute {
+                        let (address, max) = match (address, &config.wordlength) {
+                            (NodeAddress::Bits4(address), _) => (*address, 0xf),
+                            (NodeAddress::Bits7(address), WordLength::DataBits7) => (*address, 0x3f),
+                            (NodeAddress::Bits7(address), WordLength::DataBits8) => (*address, 0x7f),
+                            (NodeAddress::Bits7(address), WordLength::DataBits9) => (*address, 0xff),
+                        };
+                        if address > max {
+                            return Err(InvalidConfig);
+                        }
+                    }
+                    Self::validate_modes(&config, rcc.clocks.$pclkX().0)?;
+                    Self::validate_extended(&config)?;
+                    let (divisor, oversampling) = Self::divisor(rcc.clocks.$pclkX().0, &config)?;
+                    let (deat, dedt) = match &config.rs485 {
+                        Some(rs485) => rs485.sample_times(&oversampling).ok_or(InvalidConfig)?,
+                        None => (0, 0),
+                    };
+                    config.oversampling = oversampling;
 
                     // Enable clock for USART
                     <$USARTX>::enable(rcc);
 
-                    // Calculate correct baudrate divisor on the fly
-                    let div = (rcc.clocks.$pclkX().0 * 25) / (4 * config.baudrate.0);
-                    let mantissa = div / 100;
-                    let fraction = ((div - mantissa * 100) * 16 + 50) / 100;
-                    let mut brr = mantissa << 4 | fraction;
-
-                    if stringify!($usartX) == "lpuart1" {
-                        brr *= 256
-                    }
+                    // Make sure the USART is disabled while it's configured
+                    usart.cr1.reset();
 
                     usart
                         .brr
-                        .write(|w| unsafe { w.bits(brr) });
-
-                    // Reset other registers to disable advanced USART features
-                    usart.cr2.reset();
+                        .write(|w| unsafe { w.bits(divisor.brr) });
+
+                    // Configure the frame and disable other advanced USART features
+                    usart.cr2.write(|w| {
+                        w.swap()
+                            .bit(config.swap)
+                            .rxinv()
+                            .bit(config.rx_invert)
+                            .txinv()
+                            .bit(config.tx_invert)
+                            .datainv()
+                            .bit(config.data_invert)
+                            .msbfirst()
+                            .bit(config.msb_first);
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        unsafe {
+                            w.stop().bits(match config.stopbits {
+                                StopBits::STOP1 => 0b00,
+                                StopBits::STOP0P5 => 0b01,
+                                StopBits::STOP2 => 0b10,
+                                StopBits::STOP1P5 => 0b11,
+                            })
+                        };
+                        if let Some(MuteMode::AddressMark(address)) = &config.mute {
+                            let (addm7, address) = match address {
+                                NodeAddress::Bits4(address) => (false, *address),
+                                NodeAddress::Bits7(address) => (true, *address),
+                            };
+                            // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                            #[allow(unused_unsafe)]
+                            unsafe {
+                                w.addm7().bit(addm7).add().bits(address);
+                            }
+                        }
+                        w
+                    });
 
                     // Enable DMA
                     usart.cr3.write(|w|
@@ -354,21 +1106,31 @@ macro_rules! usart {
                             // Enable DMA
                             .dmat().enabled()
                             .dmar().enabled()
+                            // Driver enable output for RS-485
+                            .dem().bit(config.rs485.is_some())
+                            .dep().bit(match &config.rs485 {
+                                Some(Rs485 { polarity: DePolarity::ActiveLow, .. }) => true,
+                                _ => false,
+                            })
                     );
 
+                    Self::configure_modes(&usart, &config, rcc.clocks.$pclkX().0);
+
                     // Enable transmission and receiving
                     // and configure frame
                     usart.cr1.write(|w| {
-                        w.ue()
-                            .set_bit()
-                            .te()
+                        w.te()
                             .set_bit()
                             .re()
                             .set_bit()
                             .m0()
                             .bit(match config.wordlength {
-                                WordLength::DataBits8 => false,
                                 WordLength::DataBits9 => true,
+                                _ => false,
+                            }).m1()
+                            .bit(match config.wordlength {
+                                WordLength::DataBits7 => true,
+                                _ => false,
                             }).pce()
                             .bit(match config.parity {
                                 Parity::ParityNone => false,
@@ -377,51 +1139,156 @@ macro_rules! usart {
                             .bit(match config.parity {
                                 Parity::ParityOdd => true,
                                 _ => false,
-                            })
+                            }).mme()
+                            .bit(config.mute.is_some())
+                            .wake()
+                            .bit(match config.mute {
+                                Some(MuteMode::AddressMark(_)) => true,
+                                _ => false,
+                            });
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        unsafe {
+                            w.deat().bits(deat).dedt().bits(dedt)
+                        }
                     });
+                    Self::configure_extended(&usart, &config);
+
+                    usart.cr1.modify(|_, w| w.ue().set_bit());
 
-                    usart.cr2.write(|w|
-                        w.stop().bits(match config.stopbits {
-                            StopBits::STOP1 => 0b00,
-                            StopBits::STOP0P5 => 0b01,
-                            StopBits::STOP2 => 0b10,
-                            StopBits::STOP1P5 => 0b11,
-                        })
-                    );
                     Ok(Serial {
                         usart,
                         tx: Tx { _usart: PhantomData },
                         rx: Rx { _usart: PhantomData },
+                        clock: rcc.clocks.$pclkX().0,
+                        stop_mode_clock: false,
+                        config,
                     })
                 }
 
-                /// Starts listening for an interrupt event
-                pub fn listen(&mut self, event: Event) {
-                    match event {
-                        Event::Rxne => {
-                            self.usart.cr1.modify(|_, w| w.rxneie().set_bit())
-                        },
-                        Event::Txe => {
-                            self.usart.cr1.modify(|_, w| w.txeie().set_bit())
-                        },
-                        Event::Idle => {
-                            self.usart.cr1.modify(|_, w| w.idleie().set_bit())
-                        },
-                    }
+                /// Enables hardware RTS flow control on the given pin
+                ///
+                /// The RTS output is asserted (driven low) only while the
+                /// receiver is ready to accept new data.
+                pub fn enable_rts<RTS>(&mut self, rts: RTS)
+                where
+                    RTS: RtsPin<$USARTX>,
+                {
+                    rts.setup();
+                    self.reconfigure(|usart| usart.cr3.modify(|_, w| w.rtse().set_bit()));
                 }
 
-                /// Stop listening for an interrupt event
-                pub fn unlisten(&mut self, event: Event) {
-                    match event {
-                        Event::Rxne => {
-                            self.usart.cr1.modify(|_, w| w.rxneie().clear_bit())
-                        },
-                        Event::Txe => {
-                            self.usart.cr1.modify(|_, w| w.txeie().clear_bit())
-                        },
-                        Event::Idle => {
-                            self.usart.cr1.modify(|_, w| w.idleie().clear_bit())
+                /// Enables hardware CTS flow control on the given pin
+                ///
+                /// Data is only transmitted while the CTS input is low.
+                pub fn enable_cts<CTS>(&mut self, cts: CTS)
+                where
+                    CTS: CtsPin<$USARTX>,
+                {
+                    cts.setup();
+                    self.reconfigure(|usart| usart.cr3.modify(|_, w| w.ctse().set_bit()));
+                }
+
+                /// Disables hardware RTS and CTS flow control
+                pub fn disable_flow_control(&mut self) {
+                    self.reconfigure(|usart| {
+                        usart.cr3.modify(|_, w| w.rtse().clear_bit().ctse().clear_bit())
+                    });
+                }
+
+                /// Calculates the baudrate divisor for the given kernel clock
+                ///
+                /// Returns the oversampling to use along with the divisor, which
+                /// is oversampling by 8 if the baud rate can't be reached
+                /// otherwise. Fails if the baud rate is out of range or its
+                /// error exceeds the configured tolerance.
+                fn divisor(clock: u32, config: &Config) -> Result<(Divisor, Oversampling), InvalidConfig> {
+                    let baudrate = config.baudrate.0;
+
+                    let (divisor, oversampling) = if stringify!($usartX) == "lpuart1" {
+                        Divisor::lpuart(clock, baudrate).map(|divisor| (divisor, Oversampling::By16))
+                    } else {
+                        let over8 = matches!(config.oversampling, Oversampling::By8);
+                        Divisor::usart(clock, baudrate, over8)
+                            .map(|divisor| (divisor, over8))
+                            .or_else(|| Divisor::usart(clock, baudrate, true).map(|divisor| (divisor, true)))
+                            .map(|(divisor, over8)| {
+                                let oversampling = if over8 { Oversampling::By8 } else { Oversampling::By16 };
+                                (divisor, oversampling)
+                            })
+                    }
+                    .ok_or(InvalidConfig)?;
+
+                    if divisor.error_ppm(baudrate) > config.baudrate_tolerance {
+                        return Err(InvalidConfig);
+                    }
+
+                    Ok((divisor, oversampling))
+                }
+
+                /// Returns the baud rate actually achieved with the current
+                /// clock configuration
+                pub fn achieved_baudrate(&self) -> Baud {
+                    let brr = self.usart.brr.read().bits();
+                    let baudrate = if stringify!($usartX) == "lpuart1" {
+                        (256 * u64::from(self.clock) / u64::from(brr.max(1))) as u32
+                    } else {
+                        let over8 = matches!(self.config.oversampling, Oversampling::By8);
+                        Divisor::usart_baudrate(self.clock, brr, over8)
+                    };
+                    baudrate.Bd()
+                }
+
+                /// Runs `f` with the USART disabled
+                ///
+                /// Some configuration bits can only be written while UE is
+                /// cleared. Waits for an ongoing transmission to complete
+                /// before disabling the USART.
+                fn reconfigure<F>(&mut self, f: F)
+                where
+                    F: FnOnce(&$USARTX),
+                {
+                    while self.usart.isr.read().tc().bit_is_clear() {}
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    f(&self.usart);
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::Rxne => {
+                            self.usart.cr1.modify(|_, w| w.rxneie().set_bit())
+                        },
+                        Event::Txe => {
+                            self.usart.cr1.modify(|_, w| w.txeie().set_bit())
+                        },
+                        Event::Idle => {
+                            self.usart.cr1.modify(|_, w| w.idleie().set_bit())
+                        },
+                        Event::CharacterMatch => {
+                            self.usart.cr1.modify(|_, w| w.cmie().set_bit())
+                        },
+                        _ => self.set_extended_event(event, true),
+                    }
+                }
+
+                /// Stop listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::Rxne => {
+                            self.usart.cr1.modify(|_, w| w.rxneie().clear_bit())
+                        },
+                        Event::Txe => {
+                            self.usart.cr1.modify(|_, w| w.txeie().clear_bit())
+                        },
+                        Event::Idle => {
+                            self.usart.cr1.modify(|_, w| w.idleie().clear_bit())
                         },
+                        Event::CharacterMatch => {
+                            self.usart.cr1.modify(|_, w| w.cmie().clear_bit())
+                        },
+                        _ => self.set_extended_event(event, false),
                     }
                 }
 
@@ -448,8 +1315,10 @@ macro_rules! usart {
                         Some(Event::Txe)
                     } else if cr1.idleie().bit_is_set() && isr.idle().bit_is_set() {
                         Some(Event::Idle)
+                    } else if cr1.cmie().bit_is_set() && isr.cmf().bit_is_set() {
+                        Some(Event::CharacterMatch)
                     } else {
-                        None
+                        self.pending_extended_event()
                     }
                 }
 
@@ -461,6 +1330,66 @@ macro_rules! usart {
                     self.rx.check_errors()
                 }
 
+                /// Sends a break character after the current transmission
+                pub fn send_break(&mut self) {
+                    self.tx.send_break()
+                }
+
+                /// Sets the character that raises `Event::CharacterMatch`
+                ///
+                /// The character is also used by `WakeupSource::AddressMatch`.
+                /// It is stored in the same register field as the node address
+                /// of `MuteMode::AddressMark`, so an error is returned if
+                /// address mark wakeup is configured.
+                pub fn set_character_match(&mut self, character: u8) -> Result<(), InvalidConfig> {
+                    if let Some(MuteMode::AddressMark(_)) = self.config.mute {
+                        return Err(InvalidConfig);
+                    }
+
+                    // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                    #[allow(unused_unsafe)]
+                    self.reconfigure(|usart| {
+                        usart.cr2.modify(|_, w| unsafe { w.addm7().set_bit().add().bits(character) })
+                    });
+                    Ok(())
+                }
+
+                /// Puts the receiver into mute mode
+                ///
+                /// Received data is ignored until the wakeup event configured
+                /// with `Config::mute`. Has no effect if mute mode isn't
+                /// enabled.
+                pub fn enter_mute(&mut self) {
+                    self.usart.rqr.write(|w| w.mmrq().set_bit());
+                }
+
+                /// Takes the receiver out of mute mode
+                pub fn leave_mute(&mut self) {
+                    if self.config.mute.is_some() {
+                        // Clearing MME wakes up the receiver
+                        self.usart.cr1.modify(|_, w| w.mme().clear_bit());
+                        self.usart.cr1.modify(|_, w| w.mme().set_bit());
+                    }
+                }
+
+                /// Returns true if the receiver is in mute mode
+                pub fn is_muted(&self) -> bool {
+                    self.usart.isr.read().rwu().bit_is_set()
+                }
+
+                /// Returns the configured or detected baud rate
+                pub fn baudrate(&self) -> Baud {
+                    self.config.baudrate
+                }
+
+                /// Returns the configuration of this serial interface
+                ///
+                /// The baud rate reflects the result of an automatic baud rate
+                /// detection.
+                pub fn config(&self) -> &Config {
+                    &self.config
+                }
+
                 /// Clears any signaled errors without returning them.
                 pub fn clear_errors(&mut self) {
                     self.rx.clear_errors()
@@ -475,6 +1404,108 @@ macro_rules! usart {
                 }
             }
 
+            impl Serial<$USARTX> {
+                /// Turns this into an interrupt-driven serial interface
+                ///
+                /// Received bytes are stored in `rx_buffer`, bytes written
+                /// are queued in `tx_buffer`. The RXNE and TXE interrupts are
+                /// managed by the returned `BufferedSerial`, but the USART
+                /// interrupt must be unmasked in the NVIC.
+                ///
+                /// # Panics
+                ///
+                /// Panics if one of the buffers is empty.
+                pub fn buffered(
+                    mut self,
+                    tx_buffer: &'static mut [u8],
+                    rx_buffer: &'static mut [u8],
+                ) -> BufferedSerial<$USARTX> {
+                    assert!(!tx_buffer.is_empty() && !rx_buffer.is_empty());
+
+                    self.listen(Event::Rxne);
+
+                    BufferedSerial {
+                        serial: self,
+                        tx: Queue::new(tx_buffer),
+                        rx: Queue::new(rx_buffer),
+                        error: None,
+                    }
+                }
+            }
+
+            impl BufferedSerial<$USARTX> {
+                /// Receives and sends pending data
+                ///
+                /// Must be called from the interrupt handler of the USART.
+                /// Bytes received while the RX queue is full are dropped and
+                /// reported as `Error::BufferOverrun` by the next `read`.
+                pub fn on_interrupt(&mut self) {
+                    loop {
+                        match self.serial.rx.read() {
+                            Ok(byte) => {
+                                if !self.rx.push(byte) {
+                                    self.report(Error::BufferOverrun);
+                                }
+                            }
+                            Err(nb::Error::Other(error)) => self.report(error),
+                            Err(nb::Error::WouldBlock) => break,
+                        }
+                    }
+
+                    if self.serial.usart.cr1.read().txeie().bit_is_set()
+                        && self.serial.usart.isr.read().txe().bit_is_set()
+                    {
+                        match self.tx.pop() {
+                            Some(byte) => {
+                                self.serial.tx.write(byte).ok();
+                            }
+                            None => self.serial.unlisten(Event::Txe),
+                        }
+                    }
+                }
+
+                /// Stops the interrupt-driven operation
+                ///
+                /// Returns the serial interface and the TX and RX buffers.
+                /// Queued data is discarded.
+                pub fn release(mut self) -> (Serial<$USARTX>, &'static mut [u8], &'static mut [u8]) {
+                    self.serial.unlisten(Event::Rxne);
+                    self.serial.unlisten(Event::Txe);
+                    (self.serial, self.tx.release(), self.rx.release())
+                }
+            }
+
+            impl hal::serial::Read<u8> for BufferedSerial<$USARTX> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    if let Some(error) = self.error.take() {
+                        return Err(nb::Error::Other(error));
+                    }
+                    self.rx.pop().ok_or(nb::Error::WouldBlock)
+                }
+            }
+
+            impl hal::serial::Write<u8> for BufferedSerial<$USARTX> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    if self.tx.len > 0 {
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    self.serial.tx.flush()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+                    if self.tx.push(byte) {
+                        self.serial.listen(Event::Txe);
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
             impl hal::serial::Read<u8> for Serial<$USARTX> {
                 type Error = Error;
 
@@ -495,7 +1526,43 @@ macro_rules! usart {
                 }
             }
 
-            impl Rx<$USARTX> {
+            impl<WORD> Rx<$USARTX, WORD> {
+                /// Receives 9 bit words as `u16`
+                pub fn with_u16_data(self) -> Rx<$USARTX, u16> {
+                    Rx { _usart: PhantomData }
+                }
+
+                /// Receives words of up to 8 bits as `u8`
+                pub fn with_u8_data(self) -> Rx<$USARTX, u8> {
+                    Rx { _usart: PhantomData }
+                }
+
+                /// Reads a received word, without the parity bit
+                fn read_word(&mut self) -> nb::Result<u16, Error> {
+                    self.check_errors()?;
+
+                    // NOTE(unsafe) atomic read with no side effects
+                    let usart = unsafe { &*$USARTX::ptr() };
+                    let isr = usart.isr.read();
+
+                    // Check if a word is available
+                    if isr.rxne().bit_is_set() {
+                        // The parity bit, if enabled, is received in the MSB
+                        let cr1 = usart.cr1.read();
+                        let bits = if cr1.m1().bit_is_set() {
+                            7
+                        } else if cr1.m0().bit_is_set() {
+                            9
+                        } else {
+                            8
+                        } - cr1.pce().bit_is_set() as u16;
+
+                        Ok(usart.rdr.read().rdr().bits() & ((1 << bits) - 1))
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
                 /// Returns true if the line idle status is set
                 /// This reads the ISR register's IDLE bit. This bit is set by hardware
                 /// when an Idle Line is detected. And can be cleared by calling `clear_idle_interrupt`.
@@ -518,6 +1585,18 @@ macro_rules! usart {
                     icr.write(|w| w.idlecf().set_bit());
                 }
 
+                /// Returns true if the character set with `set_character_match` was received
+                pub fn is_character_match(&self) -> bool {
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    isr.cmf().bit_is_set()
+                }
+
+                /// Clear character match flag
+                pub fn clear_character_match(&self) {
+                    let icr = unsafe { &(*$USARTX::ptr()).icr };
+                    icr.write(|w| w.cmcf().set_bit());
+                }
+
                 /// Checks for reception errors that may have occurred.
                 ///
                 /// Note that multiple errors can be signaled at the same time. In that case,
@@ -561,6 +1640,76 @@ macro_rules! usart {
             /// DMA operations.
             #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
             impl Rx<$USARTX> {
+                /// Starts receiving into `buffer` using circular DMA
+                ///
+                /// The half transfer and transfer complete interrupts of the
+                /// DMA channel are enabled, so `RingBuffer::read` can be called
+                /// from the DMA interrupt handler.
+                ///
+                /// # Panics
+                ///
+                /// Panics, if `buffer` is empty or larger than 65535.
+                pub fn read_circular<Buffer, Channel>(self,
+                    dma:     &mut dma::Handle,
+                    buffer:  Pin<Buffer>,
+                    channel: Channel,
+                )
+                    -> RingBuffer<$USARTX, Channel, Buffer>
+                    where
+                        Self:           dma::Target<Channel>,
+                        Buffer:         DerefMut + 'static,
+                        Buffer::Target: AsMutSlice<Element=u8>,
+                        Channel:        dma::Channel,
+                {
+                    let num_words = (*buffer).len();
+                    assert!(num_words > 0);
+                    let ptr = (*buffer).as_ptr();
+
+                    // Safe, because we're only taking the address of a
+                    // register.
+                    let address =
+                        &unsafe { &*$USARTX::ptr() }.rdr as *const _ as u32;
+
+                    // Safe, because the trait bounds of this method guarantee
+                    // that the buffer can be written to.
+                    let mut transfer = unsafe {
+                        dma::Transfer::new(
+                            dma,
+                            self,
+                            channel,
+                            buffer,
+                            num_words,
+                            address,
+                            dma::Priority::high(),
+                            dma::Direction::peripheral_to_memory(),
+                            true,
+                        )
+                    };
+                    transfer.enable_interrupts(dma::Interrupts {
+                        half_transfer: true,
+                        transfer_complete: true,
+                        ..dma::Interrupts::default()
+                    });
+                    let transfer = transfer.start();
+
+                    // The cast to `u16` could truncate the value, but if it
+                    // does, `Transfer::new` has panicked already.
+                    RingBuffer {
+                        transfer,
+                        ptr,
+                        position: dma::ReadPosition::new(num_words as u16),
+                    }
+                }
+            }
+
+            /// DMA operations.
+            ///
+            /// 9 bit words are transferred as `u16`.
+            #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
+            impl<WORD> Rx<$USARTX, WORD>
+            where
+                WORD: dma::SupportedWordSize,
+            {
                 pub fn read_all<Buffer, Channel>(self,
                     dma:     &mut dma::Handle,
                     buffer:  Pin<Buffer>,
@@ -570,7 +1719,7 @@ macro_rules! usart {
                     where
                         Self:           dma::Target<Channel>,
                         Buffer:         DerefMut + 'static,
-                        Buffer::Target: AsMutSlice<Element=u8>,
+                        Buffer::Target: AsMutSlice<Element=WORD>,
                         Channel:        dma::Channel,
                 {
                     let num_words = (*buffer).len();
@@ -587,7 +1736,7 @@ macro_rules! usart {
                     where
                         Self:           dma::Target<Channel>,
                         Buffer:         DerefMut + 'static,
-                        Buffer::Target: AsMutSlice<Element=u8>,
+                        Buffer::Target: AsMutSlice<Element=WORD>,
                         Channel:        dma::Channel,
                 {
                     // Safe, because we're only taking the address of a
@@ -613,25 +1762,52 @@ macro_rules! usart {
                 }
             }
 
+            #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
+            impl<Channel, Buffer> RingBuffer<$USARTX, Channel, Buffer> {
+                /// Returns true if the line idle status is set
+                pub fn is_idle(&self) -> bool {
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    isr.idle().bit_is_set()
+                }
+
+                /// Clear idle line interrupt flag
+                pub fn clear_idle_interrupt(&self) {
+                    let icr = unsafe { &(*$USARTX::ptr()).icr };
+                    icr.write(|w| w.idlecf().set_bit());
+                }
+
+                /// Returns true if the character set with `set_character_match` was received
+                pub fn is_character_match(&self) -> bool {
+                    Rx::<$USARTX, u8> { _usart: PhantomData }.is_character_match()
+                }
+
+                /// Clear character match flag
+                pub fn clear_character_match(&self) {
+                    Rx::<$USARTX, u8> { _usart: PhantomData }.clear_character_match()
+                }
+
+                /// Checks for reception errors that may have occurred.
+                ///
+                /// Note that multiple errors can be signaled at the same time. In that case,
+                /// calling this function repeatedly will return the remaining errors.
+                pub fn check_errors(&mut self) -> Result<(), Error> {
+                    Rx::<$USARTX, u8> { _usart: PhantomData }.check_errors()
+                }
+            }
+
             impl hal::serial::Read<u8> for Rx<$USARTX> {
                 type Error = Error;
 
                 fn read(&mut self) -> nb::Result<u8, Error> {
-                    self.check_errors()?;
+                    self.read_word().map(|word| word as u8)
+                }
+            }
 
-                    // NOTE(unsafe) atomic read with no side effects
-                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+            impl hal::serial::Read<u16> for Rx<$USARTX, u16> {
+                type Error = Error;
 
-                    // Check if a byte is available
-                    if isr.rxne().bit_is_set() {
-                        // Read the received byte
-                        // NOTE(read_volatile) see `write_volatile` below
-                        Ok(unsafe {
-                            ptr::read_volatile(&(*$USARTX::ptr()).rdr as *const _ as *const _)
-                        })
-                    } else {
-                        Err(nb::Error::WouldBlock)
-                    }
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    self.read_word()
                 }
             }
 
@@ -639,29 +1815,41 @@ macro_rules! usart {
                 type Error = Error;
 
                 fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    self.flush_frame()
+                }
+
+                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
                     // NOTE(unsafe) atomic read with no side effects
                     let isr = unsafe { (*$USARTX::ptr()).isr.read() };
 
-                    // Check TC bit on ISR
-                    if isr.tc().bit_is_set() {
-                        // Frame complete, set the TC Clear Flag
-                        unsafe {
-                            (*$USARTX::ptr()).icr.write(|w| {w.tccf().set_bit()});
-                        }
+                    if isr.txe().bit_is_set() {
+                        // NOTE(unsafe) atomic write to stateless register
+                        // NOTE(write_volatile) 8-bit write that's not possible through the svd2rust API
+                        unsafe { ptr::write_volatile(&(*$USARTX::ptr()).tdr as *const _ as *mut _, byte) }
+
                         Ok(())
                     } else {
                         Err(nb::Error::WouldBlock)
                     }
                 }
+            }
 
-                fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            impl hal::serial::Write<u16> for Tx<$USARTX, u16> {
+                type Error = Error;
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    self.flush_frame()
+                }
+
+                fn write(&mut self, word: u16) -> nb::Result<(), Self::Error> {
                     // NOTE(unsafe) atomic read with no side effects
                     let isr = unsafe { (*$USARTX::ptr()).isr.read() };
 
                     if isr.txe().bit_is_set() {
                         // NOTE(unsafe) atomic write to stateless register
-                        // NOTE(write_volatile) 8-bit write that's not possible through the svd2rust API
-                        unsafe { ptr::write_volatile(&(*$USARTX::ptr()).tdr as *const _ as *mut _, byte) }
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        unsafe { (*$USARTX::ptr()).tdr.write(|w| w.tdr().bits(word & 0x1ff)) }
 
                         Ok(())
                     } else {
@@ -670,27 +1858,65 @@ macro_rules! usart {
                 }
             }
 
-            impl Tx<$USARTX> {
-                /// Returns true if the tx register is empty (and can accept data)
-                pub fn is_tx_empty(&self) -> bool {
-                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
-                    isr.txe().bit_is_set()
+            impl<WORD> Tx<$USARTX, WORD> {
+                /// Transmits 9 bit words as `u16`
+                pub fn with_u16_data(self) -> Tx<$USARTX, u16> {
+                    Tx { _usart: PhantomData }
                 }
-            }
 
-            #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
-            impl Tx<$USARTX> {
-                pub fn write_all<Buffer, Channel>(self,
-                    dma:     &mut dma::Handle,
-                    buffer:  Pin<Buffer>,
-                    channel: Channel,
-                )
-                    -> dma::Transfer<Self, Channel, Buffer, dma::Ready>
-                    where
-                        Self:           dma::Target<Channel>,
-                        Buffer:         Deref + 'static,
-                        Buffer::Target: AsSlice<Element=u8>,
-                        Channel:        dma::Channel,
+                /// Transmits words of up to 8 bits as `u8`
+                pub fn with_u8_data(self) -> Tx<$USARTX, u8> {
+                    Tx { _usart: PhantomData }
+                }
+
+                fn flush_frame(&mut self) -> nb::Result<(), Error> {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+
+                    // Check TC bit on ISR
+                    if isr.tc().bit_is_set() {
+                        // Frame complete, set the TC Clear Flag
+                        unsafe {
+                            (*$USARTX::ptr()).icr.write(|w| {w.tccf().set_bit()});
+                        }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Returns true if the tx register is empty (and can accept data)
+                pub fn is_tx_empty(&self) -> bool {
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    isr.txe().bit_is_set()
+                }
+
+                /// Sends a break character after the current transmission
+                pub fn send_break(&mut self) {
+                    // NOTE(unsafe) atomic write to stateless register
+                    unsafe { (*$USARTX::ptr()).rqr.write(|w| w.sbkrq().set_bit()) };
+                }
+            }
+
+            /// DMA operations.
+            ///
+            /// 9 bit words are transferred as `u16`.
+            #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
+            impl<WORD> Tx<$USARTX, WORD>
+            where
+                WORD: dma::SupportedWordSize,
+            {
+                pub fn write_all<Buffer, Channel>(self,
+                    dma:     &mut dma::Handle,
+                    buffer:  Pin<Buffer>,
+                    channel: Channel,
+                )
+                    -> dma::Transfer<Self, Channel, Buffer, dma::Ready>
+                    where
+                        Self:           dma::Target<Channel>,
+                        Buffer:         Deref + 'static,
+                        Buffer::Target: AsSlice<Element=WORD>,
+                        Channel:        dma::Channel,
                 {
                     let num_words = (*buffer).len();
                     self.write_some(dma, buffer, num_words, channel)
@@ -706,7 +1932,7 @@ macro_rules! usart {
                     where
                         Self:           dma::Target<Channel>,
                         Buffer:         Deref + 'static,
-                        Buffer::Target: AsSlice<Element=u8>,
+                        Buffer::Target: AsSlice<Element=WORD>,
                         Channel:        dma::Channel,
                 {
                     // Safe, because we're only taking the address of a
@@ -760,38 +1986,646 @@ usart! {
     USART5: (usart5, apb1_clk, Serial5Ext),
 }
 
+/// Returns the IrDA low-power prescaler that divides `pclk` closest to the
+/// nominal 1.8432 MHz
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
+fn irda_low_power_prescaler(pclk: u32) -> u32 {
+    const IRDA_LOW_POWER_FREQ: u32 = 1_843_200;
+
+    (pclk + IRDA_LOW_POWER_FREQ / 2) / IRDA_LOW_POWER_FREQ
+}
+
+/// Implements oversampling by 8, which is not available on LPUART1
+macro_rules! usart_extended {
+    ($($USARTX:ident,)+) => {
+        $(
+            impl Serial<$USARTX> {
+                fn validate_extended(_: &Config) -> Result<(), InvalidConfig> {
+                    Ok(())
+                }
+
+                fn configure_extended(usart: &$USARTX, config: &Config) {
+                    Self::set_over8(usart, matches!(config.oversampling, Oversampling::By8));
+                }
+
+                fn set_over8(usart: &$USARTX, over8: bool) {
+                    usart.cr1.modify(|_, w| w.over8().bit(over8));
+                }
+            }
+        )+
+    }
+}
+
+/// Implements the LIN, IrDA and smartcard modes, the automatic baud rate
+/// detection and the receiver timeout only supported by USART1 and USART2
+macro_rules! usart_full {
+    ($($USARTX:ident,)+) => {
+        $(
+            impl Serial<$USARTX> {
+                fn validate_modes(config: &Config, pclk: u32) -> Result<(), InvalidConfig> {
+                    if config.irda.is_some() && config.smartcard.is_some() {
+                        return Err(InvalidConfig);
+                    }
+                    if let Some(smartcard) = &config.smartcard {
+                        if smartcard.clock_prescaler == 0
+                            || smartcard.clock_prescaler > 31
+                            || smartcard.retries > 7
+                        {
+                            return Err(InvalidConfig);
+                        }
+                        if !matches!(config.wordlength, WordLength::DataBits9)
+                            || matches!(config.parity, Parity::ParityNone)
+                            || !matches!(config.stopbits, StopBits::STOP1P5)
+                        {
+                            return Err(InvalidConfig);
+                        }
+                    }
+                    if let Some(mode) = &config.irda {
+                        if config.baudrate.0 > 115_200 {
+                            return Err(InvalidConfig);
+                        }
+                        if !matches!(config.stopbits, StopBits::STOP1) {
+                            return Err(InvalidConfig);
+                        }
+                        if let IrdaMode::LowPower = mode {
+                            let psc = irda_low_power_prescaler(pclk);
+                            if psc == 0 || psc > 255 {
+                                return Err(InvalidConfig);
+                            }
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn set_extended_event(&mut self, event: Event, enable: bool) {
+                    match event {
+                        Event::LinBreak => self.usart.cr2.modify(|_, w| w.lbdie().bit(enable)),
+                        Event::ReceiverTimeout => self.usart.cr1.modify(|_, w| w.rtoie().bit(enable)),
+                        _ => {}
+                    }
+                }
+
+                fn pending_extended_event(&self) -> Option<Event> {
+                    let cr1 = self.usart.cr1.read();
+                    let cr2 = self.usart.cr2.read();
+                    let isr = self.usart.isr.read();
+
+                    if cr2.lbdie().bit_is_set() && isr.lbdf().bit_is_set() {
+                        Some(Event::LinBreak)
+                    } else if cr1.rtoie().bit_is_set() && isr.rtof().bit_is_set() {
+                        Some(Event::ReceiverTimeout)
+                    } else {
+                        None
+                    }
+                }
+
+                fn configure_modes(usart: &$USARTX, config: &Config, pclk: u32) {
+                    if let Some(mode) = &config.irda {
+                        let (low_power, psc) = match mode {
+                            // The prescaler must be 1 in normal mode
+                            IrdaMode::Normal => (false, 1),
+                            IrdaMode::LowPower => (true, irda_low_power_prescaler(pclk)),
+                        };
+
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        usart.gtpr.write(|w| unsafe { w.psc().bits(psc as u8) });
+                        usart.cr3.modify(|_, w| w.iren().set_bit().irlp().bit(low_power));
+                    }
+
+                    if let Some(smartcard) = &config.smartcard {
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        usart.gtpr.write(|w| unsafe {
+                            w.psc()
+                                .bits(smartcard.clock_prescaler)
+                                .gt()
+                                .bits(smartcard.guard_time)
+                        });
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        usart.cr3.modify(|_, w| unsafe {
+                            w.scen()
+                                .set_bit()
+                                .nack()
+                                .bit(smartcard.nack)
+                                .scarcnt()
+                                .bits(smartcard.retries)
+                        });
+                        usart.cr2.modify(|_, w| w.clken().set_bit());
+                    }
+                }
+
+                /// Enables LIN mode
+                ///
+                /// The frame must be configured with 8 data bits, no parity
+                /// and 1 stop bit, and IrDA, smartcard and half-duplex mode
+                /// must not be used. Returns an error otherwise. Use
+                /// `send_break` to transmit a LIN break and listen for
+                /// `Event::LinBreak` to detect one.
+                pub fn enable_lin(&mut self, break_length: LinBreakLength) -> Result<(), InvalidConfig> {
+                    let cr1 = self.usart.cr1.read();
+                    let cr2 = self.usart.cr2.read();
+                    let cr3 = self.usart.cr3.read();
+                    if cr1.m0().bit_is_set()
+                        || cr1.m1().bit_is_set()
+                        || cr1.pce().bit_is_set()
+                        || cr2.stop().bits() != 0b00
+                        || cr3.scen().bit_is_set()
+                        || cr3.iren().bit_is_set()
+                        || cr3.hdsel().bit_is_set()
+                    {
+                        return Err(InvalidConfig);
+                    }
+
+                    self.reconfigure(|usart| {
+                        usart.cr2.modify(|_, w| {
+                            w.linen()
+                                .set_bit()
+                                .lbdl()
+                                .bit(match break_length {
+                                    LinBreakLength::Bits10 => false,
+                                    LinBreakLength::Bits11 => true,
+                                })
+                        });
+                    });
+
+                    Ok(())
+                }
+
+                /// Disables LIN mode
+                pub fn disable_lin(&mut self) {
+                    self.reconfigure(|usart| usart.cr2.modify(|_, w| w.linen().clear_bit()));
+                }
+
+                /// Returns true if a LIN break has been detected
+                pub fn is_lin_break_detected(&self) -> bool {
+                    self.usart.isr.read().lbdf().bit_is_set()
+                }
+
+                /// Clears the LIN break detection flag
+                pub fn clear_lin_break_flag(&mut self) {
+                    self.usart.icr.write(|w| w.lbdcf().set_bit());
+                }
+
+                /// Enables automatic baud rate detection
+                ///
+                /// The baud rate is measured on the next received character.
+                /// Use `auto_baud_rate` to wait for the result.
+                pub fn enable_auto_baud(&mut self, mode: AutoBaudMode) {
+                    let abrmod = match mode {
+                        AutoBaudMode::StartBit => 0b00,
+                        AutoBaudMode::FallingEdge => 0b01,
+                        AutoBaudMode::Frame0x7F => 0b10,
+                        AutoBaudMode::Frame0x55 => 0b11,
+                    };
+                    self.reconfigure(|usart| {
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        usart.cr2.modify(|_, w| unsafe { w.abren().set_bit().abrmod().bits(abrmod) });
+                    });
+                }
+
+                /// Disables automatic baud rate detection
+                pub fn disable_auto_baud(&mut self) {
+                    self.reconfigure(|usart| usart.cr2.modify(|_, w| w.abren().clear_bit()));
+                }
+
+                /// Requests a new automatic baud rate detection
+                ///
+                /// This clears the result of a previous detection.
+                pub fn request_auto_baud(&mut self) {
+                    self.usart.rqr.write(|w| w.abrrq().set_bit());
+                }
+
+                /// Returns the detected baud rate
+                ///
+                /// Returns `Error::AutoBaudRate` if the detection failed. The
+                /// detected rate is also returned by `baudrate` afterwards.
+                pub fn auto_baud_rate(&mut self) -> nb::Result<Baud, Error> {
+                    let isr = self.usart.isr.read();
+
+                    if isr.abre().bit_is_set() {
+                        Err(nb::Error::Other(Error::AutoBaudRate))
+                    } else if isr.abrf().bit_is_set() {
+                        let brr = self.usart.brr.read().bits();
+                        let over8 = matches!(self.config.oversampling, Oversampling::By8);
+                        self.config.baudrate = Divisor::usart_baudrate(self.clock, brr, over8).Bd();
+                        Ok(self.config.baudrate)
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Enables the receiver timeout
+                ///
+                /// `Event::ReceiverTimeout` is raised when no new character is
+                /// received for `bits` bit times after the last stop bit, e.g.
+                /// 3.5 characters for Modbus RTU. The timeout is limited to
+                /// 2^24 - 1 bit times.
+                pub fn set_receiver_timeout(&mut self, bits: u32) {
+                    // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                    #[allow(unused_unsafe)]
+                    self.usart.rtor.modify(|_, w| unsafe { w.rto().bits(bits.min(0xff_ffff)) });
+                    self.usart.cr2.modify(|_, w| w.rtoen().set_bit());
+                }
+
+                /// Disables the receiver timeout
+                pub fn disable_receiver_timeout(&mut self) {
+                    self.usart.cr2.modify(|_, w| w.rtoen().clear_bit());
+                }
+            }
+
+            impl<WORD> Rx<$USARTX, WORD> {
+                /// Returns true if the receiver timeout has elapsed
+                pub fn is_receiver_timeout(&self) -> bool {
+                    let isr = unsafe { (*$USARTX::ptr()).isr.read() };
+                    isr.rtof().bit_is_set()
+                }
+
+                /// Clear receiver timeout flag
+                pub fn clear_receiver_timeout(&self) {
+                    let icr = unsafe { &(*$USARTX::ptr()).icr };
+                    icr.write(|w| w.rtocf().set_bit());
+                }
+            }
+
+            #[cfg(any(feature = "stm32l0x1", feature = "stm32l0x2", feature = "stm32l0x3"))]
+            impl<Channel, Buffer> RingBuffer<$USARTX, Channel, Buffer> {
+                /// Returns true if the receiver timeout has elapsed
+                pub fn is_receiver_timeout(&self) -> bool {
+                    Rx::<$USARTX, u8> { _usart: PhantomData }.is_receiver_timeout()
+                }
+
+                /// Clear receiver timeout flag
+                pub fn clear_receiver_timeout(&self) {
+                    Rx::<$USARTX, u8> { _usart: PhantomData }.clear_receiver_timeout()
+                }
+            }
+
+            impl<TX, CK> SerialSmartcardExt<TX, CK> for $USARTX
+                where
+                    TX: HalfDuplexPin<$USARTX>,
+                    CK: CkPin<$USARTX>,
+            {
+                fn usart_smartcard(self, tx: TX, ck: CK, config: Config, rcc: &mut Rcc) -> Result<Serial<$USARTX>, InvalidConfig> {
+                    if config.smartcard.is_none() {
+                        return Err(InvalidConfig);
+                    }
+                    let serial = Serial::<$USARTX>::init(self, config, rcc)?;
+                    tx.setup();
+                    ck.setup();
+                    Ok(serial)
+                }
+            }
+
+            /// Smartcard operations.
+            impl Tx<$USARTX> {
+                /// Sends `data` to a smartcard and waits until it is transmitted
+                ///
+                /// Returns `Error::Framing` if the card still signaled a parity
+                /// error after all retries. The characters echoed on the shared
+                /// I/O line are discarded.
+                pub fn write_smartcard(&mut self, data: &[u8]) -> Result<(), Error> {
+                    for &byte in data {
+                        block!(self.write(byte))?;
+                    }
+                    block!(self.flush())?;
+
+                    // NOTE(unsafe) atomic accesses to the ISR, ICR and RQR registers
+                    let usart = unsafe { &*$USARTX::ptr() };
+
+                    // A NACK from the card sets the framing error flag
+                    let result = if usart.isr.read().fe().bit_is_set() {
+                        usart.icr.write(|w| w.fecf().set_bit());
+                        Err(Error::Framing)
+                    } else {
+                        Ok(())
+                    };
+
+                    usart.rqr.write(|w| w.rxfrq().set_bit());
+                    usart.icr.write(|w| w.orecf().set_bit());
+
+                    result
+                }
+            }
+
+            /// Smartcard operations.
+            impl Rx<$USARTX> {
+                /// Reads exactly `buffer.len()` characters from a smartcard
+                pub fn read_smartcard(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+                    for byte in buffer.iter_mut() {
+                        *byte = block!(self.read())?;
+                    }
+                    Ok(())
+                }
+
+                /// Reads the answer-to-reset of a smartcard
+                ///
+                /// Reception stops when `buffer` is full or when no character
+                /// is received for `timeout` bit times. ISO 7816-3 specifies a
+                /// maximum of 9600 ETU between characters of the ATR. Returns
+                /// the number of received characters.
+                ///
+                /// The receiver timeout only starts after the first character,
+                /// so the first character is awaited until `timer` expires.
+                /// Start it when releasing the reset of the card, e.g. with
+                /// the ISO 7816-3 limit of 40000 card clock cycles. Returns 0
+                /// if no card answers in time.
+                pub fn read_atr<T>(&mut self, buffer: &mut [u8], timeout: u32, timer: &mut T) -> Result<usize, Error>
+                where
+                    T: hal::timer::CountDown,
+                {
+                    // NOTE(unsafe) the receiver timeout is only used by the receiver
+                    let usart = unsafe { &*$USARTX::ptr() };
+
+                    // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                    #[allow(unused_unsafe)]
+                    usart.rtor.modify(|_, w| unsafe { w.rto().bits(timeout.min(0xff_ffff)) });
+                    usart.icr.write(|w| w.rtocf().set_bit());
+                    usart.cr2.modify(|_, w| w.rtoen().set_bit());
+
+                    let mut count = 0;
+                    let result = loop {
+                        if count == buffer.len() {
+                            break Ok(count);
+                        }
+                        match self.read() {
+                            Ok(byte) => {
+                                buffer[count] = byte;
+                                count += 1;
+                            }
+                            Err(nb::Error::WouldBlock) => {
+                                if count == 0 {
+                                    if timer.wait().is_ok() {
+                                        break Ok(0);
+                                    }
+                                } else if usart.isr.read().rtof().bit_is_set() {
+                                    break Ok(count);
+                                }
+                            }
+                            Err(nb::Error::Other(error)) => break Err(error),
+                        }
+                    };
+
+                    usart.cr2.modify(|_, w| w.rtoen().clear_bit());
+                    usart.icr.write(|w| w.rtocf().set_bit());
+
+                    result
+                }
+            }
+        )+
+    }
+}
+
+/// Clock enable in Stop mode bit of CR3
+const UCESM: u32 = 1 << 23;
+
+/// Implements wakeup from Stop mode for the instances that support it
+macro_rules! usart_wakeup {
+    ($($USARTX:ident: ($sel:ident, $line:ident),)+) => {
+        $(
+            impl Serial<$USARTX> {
+                /// Switches the kernel clock to HSI16
+                ///
+                /// HSI16 keeps running in Stop mode while the USART requests
+                /// it, so it can receive while the MCU is stopped. The baud
+                /// rate is kept. Returns an error and leaves the clock source
+                /// unchanged if the baud rate can't be reached from HSI16.
+                pub fn use_hsi16(&mut self, rcc: &mut Rcc) -> Result<(), InvalidConfig> {
+                    let (divisor, oversampling) = Self::divisor(HSI_FREQ, &self.config)?;
+                    let de_times = match &self.config.rs485 {
+                        Some(rs485) => Some(rs485.sample_times(&oversampling).ok_or(InvalidConfig)?),
+                        None => None,
+                    };
+
+                    // HSI16KERON (bit 1) is missing from some PAC versions
+                    rcc.rb.cr.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 1)) });
+                    rcc.rb.cr.modify(|_, w| w.hsi16on().set_bit());
+                    while rcc.rb.cr.read().hsi16rdyf().bit_is_clear() {}
+
+                    let over8 = matches!(oversampling, Oversampling::By8);
+                    self.reconfigure(|usart| {
+                        rcc.rb.ccipr.modify(|_, w| w.$sel().hsi16());
+                        usart.brr.write(|w| unsafe { w.bits(divisor.brr) });
+                        Self::set_over8(usart, over8);
+                        // The sample time changes with the oversampling
+                        if let Some((deat, dedt)) = de_times {
+                            // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                            #[allow(unused_unsafe)]
+                            usart.cr1.modify(|_, w| unsafe { w.deat().bits(deat).dedt().bits(dedt) });
+                        }
+                    });
+                    self.config.oversampling = oversampling;
+                    self.clock = HSI_FREQ;
+                    self.stop_mode_clock = true;
+
+                    Ok(())
+                }
+
+                /// Enables wakeup from Stop mode
+                ///
+                /// The USART must be clocked by HSI16 or LSE, see `use_hsi16`,
+                /// otherwise an error is returned. The wakeup interrupt is
+                /// routed to the corresponding EXTI direct line, so the MCU
+                /// wakes up from `PWR::stop_mode`. Clear the wakeup flag with
+                /// `clear_wakeup_flag` in the interrupt handler.
+                pub fn enable_wakeup(&mut self, source: WakeupSource, exti: &mut Exti) -> Result<(), InvalidConfig> {
+                    if !self.stop_mode_clock {
+                        return Err(InvalidConfig);
+                    }
+
+                    let wus = match source {
+                        WakeupSource::AddressMatch => 0b00,
+                        WakeupSource::StartBit => 0b10,
+                        WakeupSource::RxNotEmpty => 0b11,
+                    };
+                    self.reconfigure(|usart| {
+                        // This is only unsafe for some PAC versions, so we need this to suppress the warnings.
+                        #[allow(unused_unsafe)]
+                        usart.cr3.modify(|_, w| unsafe { w.wus().bits(wus).wufie().set_bit() });
+                        // UCESM (bit 23) is missing from some PAC versions
+                        usart.cr3.modify(|r, w| unsafe { w.bits(r.bits() | UCESM) });
+                    });
+                    self.usart.cr1.modify(|_, w| w.uesm().set_bit());
+                    exti.listen_direct(DirectLine::$line);
+
+                    Ok(())
+                }
+
+                /// Disables wakeup from Stop mode
+                pub fn disable_wakeup(&mut self, exti: &mut Exti) {
+                    exti.unlisten(DirectLine::$line);
+                    self.usart.cr1.modify(|_, w| w.uesm().clear_bit());
+                    self.usart.cr3.modify(|_, w| w.wufie().clear_bit());
+                    // UCESM (bit 23) is missing from some PAC versions
+                    self.usart.cr3.modify(|r, w| unsafe { w.bits(r.bits() & !UCESM) });
+                }
+
+                /// Returns true if the USART woke the MCU up
+                pub fn is_wakeup(&self) -> bool {
+                    self.usart.isr.read().wuf().bit_is_set()
+                }
+
+                /// Clears the wakeup flag
+                pub fn clear_wakeup_flag(&mut self) {
+                    self.usart.icr.write(|w| w.wucf().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
+usart_wakeup! {
+    LPUART1: (lpuart1sel, Lpuart1),
+    USART2: (usart2sel, Usart2),
+}
+
+#[cfg(any(feature = "io-STM32L051", feature = "io-STM32L071"))]
+usart_wakeup! {
+    USART1: (usart1sel, Usart1),
+}
+
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
+usart_extended! {
+    USART2,
+}
+
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
+usart_full! {
+    USART2,
+}
+
+#[cfg(any(feature = "io-STM32L051", feature = "io-STM32L071"))]
+usart_extended! {
+    USART1,
+}
+
+#[cfg(any(feature = "io-STM32L051", feature = "io-STM32L071"))]
+usart_full! {
+    USART1,
+}
+
+#[cfg(feature = "io-STM32L071")]
+usart_extended! {
+    USART4,
+    USART5,
+}
+
+/// Rejects the modes and ignores the events only supported by USART1 and
+/// USART2
+macro_rules! usart_basic_modes {
+    ($($USARTX:ident,)+) => {
+        $(
+            impl Serial<$USARTX> {
+                fn validate_modes(config: &Config, _: u32) -> Result<(), InvalidConfig> {
+                    if config.irda.is_some() || config.smartcard.is_some() {
+                        return Err(InvalidConfig);
+                    }
+                    Ok(())
+                }
+
+                fn configure_modes(_: &$USARTX, _: &Config, _: u32) {}
+
+                fn set_extended_event(&mut self, _: Event, _: bool) {}
+
+                fn pending_extended_event(&self) -> Option<Event> {
+                    None
+                }
+            }
+        )+
+    }
+}
+
+#[cfg(feature = "io-STM32L071")]
+usart_basic_modes! {
+    USART4,
+    USART5,
+}
+
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
+usart_basic_modes! {
+    LPUART1,
+}
+
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
 impl Serial<LPUART1> {
-    /// Switches LPUART1 clock course to LSE
+    fn validate_extended(config: &Config) -> Result<(), InvalidConfig> {
+        if let Oversampling::By8 = config.oversampling {
+            return Err(InvalidConfig);
+        }
+        Ok(())
+    }
+
+    fn configure_extended(_: &LPUART1, _: &Config) {}
+
+    fn set_over8(_: &LPUART1, _: bool) {}
+}
+
+#[cfg(any(
+    feature = "io-STM32L021",
+    feature = "io-STM32L031",
+    feature = "io-STM32L051",
+    feature = "io-STM32L071",
+))]
+impl Serial<LPUART1> {
+    /// Switches LPUART1 clock source to LSE
     ///
     /// Consumes LSE token, to get guarantee that
     /// LSE clocks are configured.
     ///
-    /// At the moment configured baudrate is ignored
-    /// and LPUART1 is forced to 9600 when clocked by LSE,
-    /// assuming that LSE is 32768
-    /// (and it must be so according to RM).
-    pub fn use_lse(&mut self, rcc: &mut Rcc, _: &LSE) {
-        //Disable transmitter
-        self.usart.cr1.modify(|_, w| w.te().disabled());
-        while self.usart.isr.read().tc().bit_is_clear() {}
-
-        //Disable LPUART1
-        self.usart.cr1.modify(|_, w| w.ue().disabled());
-
-        //Reconfigure LPUART to use LSE
-        rcc.rb.ccipr.modify(|_, w| w.lpuart1sel().lse());
+    /// The configured baud rate is kept. LSE is assumed to be 32768 Hz (and
+    /// it must be so according to RM), which supports baud rates up to 9600.
+    /// Returns an error and leaves the clock source unchanged if the baud
+    /// rate can't be reached within the configured tolerance.
+    pub fn use_lse(&mut self, rcc: &mut Rcc, _: &LSE) -> Result<(), InvalidConfig> {
+        const LSE_FREQ: u32 = 32_768;
+
+        if self.config.baudrate.0 > 9600 {
+            return Err(InvalidConfig);
+        }
+        let (divisor, _) = Self::divisor(LSE_FREQ, &self.config)?;
 
-        //Recalculate baudrate
-        //TODO requested baudrate value from the config should be stored somehow and used here
-        //but at the moment hardcoded 9600 will be used
-        //LSE is assumed to be 32768Hz, as RM says that LSE should only be 32768.
-        let brr = 256 * 32768 / 9600;
-        self.usart.brr.write(|w| unsafe { w.bits(brr) });
+        self.reconfigure(|usart| {
+            //Reconfigure LPUART to use LSE
+            rcc.rb.ccipr.modify(|_, w| w.lpuart1sel().lse());
+            usart.brr.write(|w| unsafe { w.bits(divisor.brr) });
+        });
+        self.clock = LSE_FREQ;
+        self.stop_mode_clock = true;
 
-        // Enable LPUART1
-        self.usart
-            .cr1
-            .write(|w| w.ue().set_bit().te().set_bit().re().set_bit());
+        Ok(())
     }
 }
 
@@ -808,6 +2642,23 @@ where
     }
 }
 
+/// Queues the string without blocking
+///
+/// Returns `fmt::Error` if the TX queue is full. The bytes that fit are
+/// queued anyway, the rest of the string is dropped.
+impl<USART> fmt::Write for BufferedSerial<USART>
+where
+    BufferedSerial<USART>: hal::serial::Write<u8>,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write(byte).map_err(|_| fmt::Error)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl<USART> fmt::Write for Tx<USART>
 where
     Tx<USART>: hal::serial::Write<u8>,
@@ -820,3 +2671,97 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usart_divisor() {
+        // 115200 baud from HSI16
+        let d = Divisor::usart(16_000_000, 115_200, false).unwrap();
+        assert_eq!(d.brr, 139);
+        assert_eq!(d.baudrate, 115_107);
+        assert_eq!(d.error_ppm(115_200), 807);
+
+        // The divisor must be at least 16 when oversampling by 16
+        assert_eq!(
+            Divisor::usart(16_000_000, 1_000_000, false).unwrap().brr,
+            16
+        );
+        assert!(Divisor::usart(16_000_000, 2_000_000, false).is_none());
+
+        // The divisor must not exceed 16 bits
+        assert!(Divisor::usart(32_000_000, 300, false).is_none());
+        assert!(Divisor::usart(16_000_000, 0, false).is_none());
+    }
+
+    #[test]
+    fn usart_divisor_over8() {
+        // 2 Mbaud is only reachable when oversampling by 8
+        let d = Divisor::usart(16_000_000, 2_000_000, true).unwrap();
+        assert_eq!(d.brr, 0x10);
+        assert_eq!(d.baudrate, 2_000_000);
+
+        // BRR[2:0] holds USARTDIV[3:0] shifted right by one
+        let d = Divisor::usart(16_000_000, 115_200, true).unwrap();
+        assert_eq!(d.brr, (278 & !0xf) | ((278 & 0xf) >> 1));
+        assert_eq!(d.baudrate, 115_107);
+        assert_eq!(Divisor::usart_baudrate(16_000_000, d.brr, true), d.baudrate);
+
+        assert!(Divisor::usart(16_000_000, 4_000_000, true).is_none());
+    }
+
+    #[test]
+    fn queue() {
+        let mut buffer = [0; 3];
+        let mut queue = Queue::new(&mut buffer);
+        assert_eq!(queue.pop(), None);
+
+        assert!(queue.push(1));
+        assert!(queue.push(2));
+        assert!(queue.push(3));
+        assert!(!queue.push(4));
+        assert_eq!(queue.pop(), Some(1));
+
+        // Wraps around the end of the buffer
+        assert!(queue.push(5));
+        assert_eq!(queue.len, 3);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn rs485_sample_times() {
+        let rs485 = Rs485::default().assertion_time(31).deassertion_time(4);
+        assert_eq!(rs485.sample_times(&Oversampling::By16), Some((31, 4)));
+        assert_eq!(rs485.sample_times(&Oversampling::By8), Some((16, 2)));
+
+        // Twice the range is available when oversampling by 8
+        let rs485 = Rs485::default().assertion_time(62).deassertion_time(63);
+        assert_eq!(rs485.sample_times(&Oversampling::By16), None);
+        assert_eq!(rs485.sample_times(&Oversampling::By8), None);
+        let rs485 = rs485.deassertion_time(61);
+        assert_eq!(rs485.sample_times(&Oversampling::By8), Some((31, 31)));
+        let rs485 = rs485.deassertion_time(255);
+        assert_eq!(rs485.sample_times(&Oversampling::By8), None);
+    }
+
+    #[test]
+    fn lpuart_divisor() {
+        // 9600 baud from LSE
+        let d = Divisor::lpuart(32_768, 9600).unwrap();
+        assert_eq!(d.brr, 874);
+        assert_eq!(d.baudrate, 9597);
+        assert!(d.error_ppm(9600) < 1000);
+
+        // Lower baud rates are fine, as long as the divisor fits 20 bits
+        assert_eq!(Divisor::lpuart(32_768, 300).unwrap().brr, 27_962);
+        assert!(Divisor::lpuart(16_000_000, 300).is_none());
+
+        // The divisor must be at least 0x300
+        assert!(Divisor::lpuart(32_768, 19_200).is_none());
+    }
+}
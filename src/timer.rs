@@ -1,4 +1,5 @@
 //! Timers
+use crate::adc;
 use crate::hal::timer::{CountDown, Periodic};
 use crate::pac::{tim2, tim21, tim22, tim6, TIM2, TIM21, TIM22, TIM3, TIM6};
 use crate::rcc::{Clocks, Enable, Rcc, Reset};
@@ -100,6 +101,20 @@ impl<T: GeneralPurposeTimer> Timer<T> {
     }
 }
 
+impl<T> Timer<T>
+where
+    T: GeneralPurposeTimer + adc::TrgoTrigger,
+{
+    /// Returns the ADC trigger that is connected to this timer's trigger output
+    ///
+    /// The event that is put out on the trigger output (TRGO) is selected with
+    /// `select_master_mode`. Pass the returned trigger to
+    /// [`adc::Adc::start`] to start ADC conversions on that event.
+    pub fn adc_trigger(&self) -> adc::Trigger {
+        T::TRIGGER
+    }
+}
+
 macro_rules! timers {
     ($($TIM:ident: ($tim:ident, $timclk:ident, $mms:ty),)+) => {
         $(
@@ -164,6 +179,9 @@ macro_rules! timers {
                 }
 
                 /// Select master mode
+                ///
+                /// See [`Timer::adc_trigger`], if the trigger output is used
+                /// to start ADC conversions.
                 pub fn select_master_mode(&mut self,
                     variant: <$TIM as GeneralPurposeTimer>::MasterMode,
                 ) {